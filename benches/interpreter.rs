@@ -0,0 +1,96 @@
+// Times the interpreter loop on a few small programs, run with `cargo bench`. Each program is parsed
+// once and run several times on a fresh `Vm`, the best run is reported so a noisy machine skews the
+// numbers as little as possible. Compare the output before and after a change to `Vm::execute`.
+use asm::interpreter::Vm;
+use asm::parser::parse;
+use asm::program::Program;
+use asm::span::Source;
+use std::io;
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 5;
+
+// Calls, arguments and arithmetic, the same as `tests/fib_recurse.asm`.
+const FIB_RECURSE: &str = "proc fib
+    decr
+    jle retOne
+    getarg 0
+    call fib
+    getarg 0
+    decr
+    call fib
+    add
+    setarg 0
+    pop
+    ret
+
+    label retOne
+        pushint 1
+        ret
+end
+
+pushint 27
+call fib
+prnt";
+
+// The cheapest instructions there are, so this is mostly the cost of dispatching them.
+const COUNTDOWN: &str = "pushint 10000000
+label loop
+    decr
+    dup
+    jne loop";
+
+// Pushes, swaps and stores values of every simple type.
+const VALUES: &str = "pushint 1000000
+label loop
+    pushflt 1.5
+    pushdbl 2.5
+    pushstr \"abc\"
+    swap
+    mov 0 -1
+    pop
+    pop
+    pop
+    decr
+    dup
+    jne loop";
+
+fn bench(name: &str, text: &str) {
+    let source = Source::new(name, text.to_string());
+    let program = parse(&source).unwrap_or_else(|e| panic!("{}", e.render(&source)));
+    let mut best = Duration::MAX;
+
+    for _ in 0..RUNS {
+        let mut vm = Vm::new(&program);
+        vm.set_output(io::sink());
+
+        let start = Instant::now();
+        vm.run().unwrap_or_else(|e| panic!("{}", e.render(&source)));
+        best = best.min(start.elapsed());
+    }
+
+    let steps = count_steps(&program);
+    println!("{:<12} {:>8.1} ms {:>6.2} ns/instruction",
+             name,
+             best.as_secs_f64() * 1e3,
+             best.as_nanos() as f64 / steps as f64);
+}
+
+// How many instructions the program executes, stepping it once more without timing it.
+fn count_steps(program: &Program) -> u64 {
+    let mut vm = Vm::new(program);
+    vm.set_output(io::sink());
+
+    let mut steps = 0;
+    while vm.step().unwrap() {
+        steps += 1;
+    }
+
+    steps
+}
+
+fn main() {
+    bench("fib_recurse", FIB_RECURSE);
+    bench("countdown", COUNTDOWN);
+    bench("values", VALUES);
+}
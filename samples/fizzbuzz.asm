@@ -80,9 +80,9 @@ proc loop
 
     label break
         ; cleanup
-        clsreg
+        clrreg
         dlcreg
-        clsstk
+        clrstk
         dlcstk
         ret
 
@@ -0,0 +1,735 @@
+use crate::error::{ErrorKind, Result};
+use crate::instructions::Instruction;
+use crate::interpreter::OverflowMode;
+use crate::program::Program;
+use crate::span::{Span, Spanned};
+use crate::stack::Pointer;
+use std::collections::{BTreeMap, HashMap};
+
+// Layout of an `.asmb` file, all integers are LEB128 varints unless noted otherwise.
+//
+// magic       b"ASMB"
+// version     u16, little endian
+// constants   count, then per constant a tag byte followed by
+//                 CONST_STR    length, utf-8 bytes
+//                 CONST_FLOAT  f32, little endian
+//                 CONST_DOUBLE f64, little endian
+// code        count, then per instruction an opcode byte followed by its operands. Strings,
+//             floats and doubles are constant indices, pointers are already resolved instruction indices
+// spans       line, col, len for every instruction, used for diagnostics
+pub const MAGIC: &[u8; 4] = b"ASMB";
+pub const VERSION: u16 = 1;
+
+const CONST_STR: u8 = 0;
+const CONST_FLOAT: u8 = 1;
+const CONST_DOUBLE: u8 = 2;
+
+mod op {
+    pub const PUSH_INT: u8 = 0;
+    pub const PUSH_FLT: u8 = 1;
+    pub const PUSH_STR: u8 = 2;
+    pub const POP: u8 = 3;
+    pub const DUP: u8 = 4;
+    pub const SWAP: u8 = 5;
+    pub const CLR_STK: u8 = 6;
+    pub const DLC_STK: u8 = 7;
+    pub const ADD: u8 = 8;
+    pub const ADD_F: u8 = 9;
+    pub const SUB: u8 = 10;
+    pub const SUB_F: u8 = 11;
+    pub const INCR: u8 = 12;
+    pub const DECR: u8 = 13;
+    pub const MUL: u8 = 14;
+    pub const MUL_F: u8 = 15;
+    pub const DIV: u8 = 16;
+    pub const DIV_F: u8 = 17;
+    pub const MOD: u8 = 18;
+    pub const MOD_F: u8 = 19;
+    pub const MOV: u8 = 20;
+    pub const LD: u8 = 21;
+    pub const DMP_HASH: u8 = 22;
+    pub const CLR_HASH: u8 = 23;
+    pub const DLC_HASH: u8 = 24;
+    pub const DMP_REG: u8 = 25;
+    pub const CLR_REG: u8 = 26;
+    pub const DLC_REG: u8 = 27;
+    pub const CMP: u8 = 28;
+    pub const INT_HAS: u8 = 29;
+    pub const STR_HAS: u8 = 30;
+    pub const FLT_HAS: u8 = 31;
+    pub const JMP: u8 = 32;
+    pub const JE: u8 = 33;
+    pub const JFE: u8 = 34;
+    pub const JNE: u8 = 35;
+    pub const JFNE: u8 = 36;
+    pub const JGT: u8 = 37;
+    pub const JFGT: u8 = 38;
+    pub const JLT: u8 = 39;
+    pub const JFLT: u8 = 40;
+    pub const JGE: u8 = 41;
+    pub const JFGE: u8 = 42;
+    pub const JLE: u8 = 43;
+    pub const JFLE: u8 = 44;
+    pub const GET: u8 = 45;
+    pub const SET: u8 = 46;
+    pub const GET_ARG: u8 = 47;
+    pub const SET_ARG: u8 = 48;
+    pub const NOOP: u8 = 49;
+    pub const PRNT: u8 = 50;
+    pub const PRNT_STR: u8 = 51;
+    pub const PRNTLN: u8 = 52;
+    pub const PRNT_C: u8 = 53;
+    pub const PRNT_CLN: u8 = 54;
+    pub const PRNT_STK: u8 = 55;
+    pub const PRNT_REG: u8 = 56;
+    pub const CALL: u8 = 57;
+    pub const NCALL: u8 = 58;
+    pub const RET: u8 = 59;
+    pub const TRACE_ON: u8 = 60;
+    pub const TRACE_OFF: u8 = 61;
+    pub const OVERFLOW_TRAP: u8 = 62;
+    pub const OVERFLOW_WRAP: u8 = 63;
+    pub const OVERFLOW_SATURATE: u8 = 64;
+    pub const LENIENT_ON: u8 = 65;
+    pub const LENIENT_OFF: u8 = 66;
+    pub const PUSH_DBL: u8 = 67;
+    pub const ADD_D: u8 = 68;
+    pub const SUB_D: u8 = 69;
+    pub const MUL_D: u8 = 70;
+    pub const DIV_D: u8 = 71;
+    pub const MOD_D: u8 = 72;
+    pub const JDE: u8 = 73;
+    pub const JDNE: u8 = 74;
+    pub const JDGT: u8 = 75;
+    pub const JDLT: u8 = 76;
+    pub const JDGE: u8 = 77;
+    pub const JDLE: u8 = 78;
+    pub const ITOF: u8 = 79;
+    pub const FTOI: u8 = 80;
+    pub const ITOD: u8 = 81;
+    pub const DTOI: u8 = 82;
+    pub const FTOD: u8 = 83;
+    pub const DTOF: u8 = 84;
+    pub const ITOS: u8 = 85;
+    pub const STOI: u8 = 86;
+    pub const FTOS: u8 = 87;
+    pub const STOF: u8 = 88;
+    pub const DTOS: u8 = 89;
+    pub const STOD: u8 = 90;
+    pub const STR_CAT: u8 = 91;
+    pub const STR_LEN: u8 = 92;
+    pub const SUB_STR: u8 = 93;
+    pub const CHAR_AT: u8 = 94;
+    pub const STR_FIND: u8 = 95;
+    pub const STR_UP: u8 = 96;
+    pub const STR_LOW: u8 = 97;
+    pub const STR_TRIM: u8 = 98;
+    pub const STR_SPLIT: u8 = 99;
+    pub const STR_CMP: u8 = 100;
+    pub const AND: u8 = 101;
+    pub const OR: u8 = 102;
+    pub const XOR: u8 = 103;
+    pub const NOT: u8 = 104;
+    pub const SHL: u8 = 105;
+    pub const SHR: u8 = 106;
+    pub const LSHR: u8 = 107;
+    pub const POPCNT: u8 = 108;
+    pub const ROTL: u8 = 109;
+    pub const ROTR: u8 = 110;
+    pub const A_NEW: u8 = 111;
+    pub const A_LOAD: u8 = 112;
+    pub const A_STORE: u8 = 113;
+    pub const A_LEN: u8 = 114;
+    pub const A_PUSH: u8 = 115;
+    pub const A_POP: u8 = 116;
+    pub const M_NEW: u8 = 117;
+    pub const M_SET: u8 = 118;
+    pub const M_GET: u8 = 119;
+    pub const M_DEL: u8 = 120;
+    pub const M_LEN: u8 = 121;
+    pub const M_KEYS: u8 = 122;
+    pub const RD_LN: u8 = 123;
+    pub const RD_INT: u8 = 124;
+    pub const RD_FLT: u8 = 125;
+    pub const RD_C: u8 = 126;
+    pub const ARGC: u8 = 127;
+    pub const ARGV: u8 = 128;
+    pub const EXIT: u8 = 129;
+    pub const F_OPEN: u8 = 130;
+    pub const F_READ_LN: u8 = 131;
+    pub const F_READ_ALL: u8 = 132;
+    pub const F_WRITE: u8 = 133;
+    pub const F_CLOSE: u8 = 134;
+}
+
+pub fn is_bytecode(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+pub fn serialize(program: &Program) -> Vec<u8> {
+    let mut code = Writer::default();
+
+    write_varint(&mut code.bytes, program.instructions.len() as u64);
+    for instruction in &program.instructions {
+        code.instruction(&instruction.node);
+    }
+
+    for instruction in &program.instructions {
+        let span = instruction.span;
+        write_varint(&mut code.bytes, span.line as u64);
+        write_varint(&mut code.bytes, span.col as u64);
+        write_varint(&mut code.bytes, span.len as u64);
+    }
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+
+    write_varint(&mut bytes, code.constants.len() as u64);
+    for constant in &code.constants {
+        match constant {
+            Constant::Str(s) => {
+                bytes.push(CONST_STR);
+                write_varint(&mut bytes, s.len() as u64);
+                bytes.extend_from_slice(s.as_bytes());
+            }
+            Constant::Float(f) => {
+                bytes.push(CONST_FLOAT);
+                bytes.extend_from_slice(&f.to_le_bytes());
+            }
+            Constant::Double(d) => {
+                bytes.push(CONST_DOUBLE);
+                bytes.extend_from_slice(&d.to_le_bytes());
+            }
+        }
+    }
+
+    bytes.extend_from_slice(&code.bytes);
+    bytes
+}
+
+// Loads a serialized program, `name` is used in place of the source file name in diagnostics.
+// Label and procedure names are not part of the format, so the returned program has none.
+pub fn deserialize(name: &str, bytes: &[u8]) -> Result<Program> {
+    let mut r = Reader { bytes, pos: 0 };
+
+    if r.take(MAGIC.len())? != MAGIC {
+        return Err(invalid("missing ASMB header"));
+    }
+
+    let version = u16::from_le_bytes([r.byte()?, r.byte()?]);
+    if version != VERSION {
+        return Err(invalid(&format!("unsupported version {}, expected {}", version, VERSION)));
+    }
+
+    let constants = (0..r.varint()?).map(|_| r.constant()).collect::<Result<Vec<_>>>()?;
+    let instructions = (0..r.varint()?).map(|_| r.instruction(&constants)).collect::<Result<Vec<_>>>()?;
+    let instructions = instructions.into_iter()
+                                   .map(|node| {
+                                       Ok(Spanned { node,
+                                                    span: Span { line: r.varint()? as usize,
+                                                                 col: r.varint()? as usize,
+                                                                 len: r.varint()? as usize } })
+                                   })
+                                   .collect::<Result<Vec<_>>>()?;
+
+    if r.pos != bytes.len() {
+        return Err(invalid("trailing bytes after the span table"));
+    }
+
+    if let Some(p) = instructions.iter().filter_map(|i| i.node.target()).find(|p| *p > instructions.len()) {
+        return Err(invalid(&format!("pointer {} is out of bounds", p)));
+    }
+
+    // A call lands just past the `proc` line, so it can never point at the first instruction.
+    if instructions.iter().any(|i| matches!(i.node, Instruction::Call(0))) {
+        return Err(invalid("`call` to instruction 0, which can't follow a `proc`"));
+    }
+
+    Ok(Program { name: name.to_string(),
+                 instructions,
+                 labels: BTreeMap::new(),
+                 procedures: BTreeMap::new() })
+}
+
+fn invalid(reason: &str) -> crate::error::Error {
+    ErrorKind::InvalidBytecode(reason.to_string()).into()
+}
+
+enum Constant {
+    Str(String),
+    Float(f32),
+    Double(f64),
+}
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+    constants: Vec<Constant>,
+    strings: HashMap<String, usize>,
+    floats: HashMap<u32, usize>,
+    doubles: HashMap<u64, usize>,
+}
+
+impl Writer {
+    fn op(&mut self, op: u8) {
+        self.bytes.push(op);
+    }
+
+    fn int(&mut self, v: isize) {
+        // zigzag, so small negative numbers stay small
+        write_varint(&mut self.bytes, ((v << 1) ^ (v >> (isize::BITS - 1))) as u64);
+    }
+
+    fn pointer(&mut self, p: Pointer) {
+        write_varint(&mut self.bytes, p as u64);
+    }
+
+    fn string(&mut self, s: &str) {
+        let i = *self.strings.entry(s.to_string()).or_insert_with(|| {
+                                                      self.constants.push(Constant::Str(s.to_string()));
+                                                      self.constants.len() - 1
+                                                  });
+
+        write_varint(&mut self.bytes, i as u64);
+    }
+
+    fn float(&mut self, f: f32) {
+        let i = *self.floats.entry(f.to_bits()).or_insert_with(|| {
+                                                   self.constants.push(Constant::Float(f));
+                                                   self.constants.len() - 1
+                                               });
+
+        write_varint(&mut self.bytes, i as u64);
+    }
+
+    fn double(&mut self, d: f64) {
+        let i = *self.doubles.entry(d.to_bits()).or_insert_with(|| {
+                                                    self.constants.push(Constant::Double(d));
+                                                    self.constants.len() - 1
+                                                });
+
+        write_varint(&mut self.bytes, i as u64);
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) {
+        use Instruction::*;
+
+        match instruction {
+            PushInt(d) => {
+                self.op(op::PUSH_INT);
+                self.int(*d);
+            }
+            PushFlt(d) => {
+                self.op(op::PUSH_FLT);
+                self.float(*d);
+            }
+            PushStr(d) => {
+                self.op(op::PUSH_STR);
+                self.string(d);
+            }
+            Pop => self.op(op::POP),
+            Dup => self.op(op::DUP),
+            Swap => self.op(op::SWAP),
+            ClrStk => self.op(op::CLR_STK),
+            DlcStk => self.op(op::DLC_STK),
+            Add => self.op(op::ADD),
+            AddF => self.op(op::ADD_F),
+            Sub => self.op(op::SUB),
+            SubF => self.op(op::SUB_F),
+            Incr => self.op(op::INCR),
+            Decr => self.op(op::DECR),
+            Mul => self.op(op::MUL),
+            MulF => self.op(op::MUL_F),
+            Div => self.op(op::DIV),
+            DivF => self.op(op::DIV_F),
+            Mod => self.op(op::MOD),
+            ModF => self.op(op::MOD_F),
+            Mov(d, p) => {
+                self.op(op::MOV);
+                self.int(*d);
+                self.int(*p);
+            }
+            Ld(d) => {
+                self.op(op::LD);
+                self.int(*d);
+            }
+            DmpHash(p) => {
+                self.op(op::DMP_HASH);
+                self.int(*p);
+            }
+            ClrHash => self.op(op::CLR_HASH),
+            DlcHash => self.op(op::DLC_HASH),
+            DmpReg(p) => {
+                self.op(op::DMP_REG);
+                self.int(*p);
+            }
+            ClrReg => self.op(op::CLR_REG),
+            DlcReg => self.op(op::DLC_REG),
+            Cmp(p) => self.pointer_op(op::CMP, *p),
+            IntHas(p) => self.pointer_op(op::INT_HAS, *p),
+            StrHas(p) => self.pointer_op(op::STR_HAS, *p),
+            FltHas(p) => self.pointer_op(op::FLT_HAS, *p),
+            Jmp(p) => self.pointer_op(op::JMP, *p),
+            JE(p) => self.pointer_op(op::JE, *p),
+            JFE(p) => self.pointer_op(op::JFE, *p),
+            JNE(p) => self.pointer_op(op::JNE, *p),
+            JFNE(p) => self.pointer_op(op::JFNE, *p),
+            JGT(p) => self.pointer_op(op::JGT, *p),
+            JFGT(p) => self.pointer_op(op::JFGT, *p),
+            JLT(p) => self.pointer_op(op::JLT, *p),
+            JFLT(p) => self.pointer_op(op::JFLT, *p),
+            JGE(p) => self.pointer_op(op::JGE, *p),
+            JFGE(p) => self.pointer_op(op::JFGE, *p),
+            JLE(p) => self.pointer_op(op::JLE, *p),
+            JFLE(p) => self.pointer_op(op::JFLE, *p),
+            Get(p) => self.pointer_op(op::GET, *p),
+            Set(p) => self.pointer_op(op::SET, *p),
+            GetArg(p) => self.pointer_op(op::GET_ARG, *p),
+            SetArg(p) => self.pointer_op(op::SET_ARG, *p),
+            Noop => self.op(op::NOOP),
+            Prnt => self.op(op::PRNT),
+            PrntStr(d) => {
+                self.op(op::PRNT_STR);
+                self.string(d);
+            }
+            Prntln => self.op(op::PRNTLN),
+            PrntC => self.op(op::PRNT_C),
+            PrntCln => self.op(op::PRNT_CLN),
+            PrntStk => self.op(op::PRNT_STK),
+            PrntReg => self.op(op::PRNT_REG),
+            RdLn(p) => self.pointer_op(op::RD_LN, *p),
+            RdInt(p) => self.pointer_op(op::RD_INT, *p),
+            RdFlt(p) => self.pointer_op(op::RD_FLT, *p),
+            RdC => self.op(op::RD_C),
+            Argc => self.op(op::ARGC),
+            Argv => self.op(op::ARGV),
+            Exit(d) => {
+                self.op(op::EXIT);
+                self.int(*d as isize);
+            }
+            FOpen => self.op(op::F_OPEN),
+            FReadLn(p) => self.pointer_op(op::F_READ_LN, *p),
+            FReadAll => self.op(op::F_READ_ALL),
+            FWrite => self.op(op::F_WRITE),
+            FClose => self.op(op::F_CLOSE),
+            Call(p) => self.pointer_op(op::CALL, *p),
+            NCall(d) => {
+                self.op(op::NCALL);
+                self.string(d);
+            }
+            Ret => self.op(op::RET),
+            Trace(true) => self.op(op::TRACE_ON),
+            Trace(false) => self.op(op::TRACE_OFF),
+            Overflow(OverflowMode::Trap) => self.op(op::OVERFLOW_TRAP),
+            Overflow(OverflowMode::Wrap) => self.op(op::OVERFLOW_WRAP),
+            Overflow(OverflowMode::Saturate) => self.op(op::OVERFLOW_SATURATE),
+            Lenient(true) => self.op(op::LENIENT_ON),
+            Lenient(false) => self.op(op::LENIENT_OFF),
+            PushDbl(d) => {
+                self.op(op::PUSH_DBL);
+                self.double(*d);
+            }
+            AddD => self.op(op::ADD_D),
+            SubD => self.op(op::SUB_D),
+            MulD => self.op(op::MUL_D),
+            DivD => self.op(op::DIV_D),
+            ModD => self.op(op::MOD_D),
+            JDE(p) => self.pointer_op(op::JDE, *p),
+            JDNE(p) => self.pointer_op(op::JDNE, *p),
+            JDGT(p) => self.pointer_op(op::JDGT, *p),
+            JDLT(p) => self.pointer_op(op::JDLT, *p),
+            JDGE(p) => self.pointer_op(op::JDGE, *p),
+            JDLE(p) => self.pointer_op(op::JDLE, *p),
+            ItoF => self.op(op::ITOF),
+            FtoI => self.op(op::FTOI),
+            ItoD => self.op(op::ITOD),
+            DtoI => self.op(op::DTOI),
+            FtoD => self.op(op::FTOD),
+            DtoF => self.op(op::DTOF),
+            ItoS => self.op(op::ITOS),
+            StoI => self.op(op::STOI),
+            FtoS => self.op(op::FTOS),
+            StoF => self.op(op::STOF),
+            DtoS => self.op(op::DTOS),
+            StoD => self.op(op::STOD),
+            StrCat => self.op(op::STR_CAT),
+            StrLen => self.op(op::STR_LEN),
+            SubStr => self.op(op::SUB_STR),
+            CharAt => self.op(op::CHAR_AT),
+            StrFind => self.op(op::STR_FIND),
+            StrUp => self.op(op::STR_UP),
+            StrLow => self.op(op::STR_LOW),
+            StrTrim => self.op(op::STR_TRIM),
+            StrSplit => self.op(op::STR_SPLIT),
+            StrCmp => self.op(op::STR_CMP),
+            And => self.op(op::AND),
+            Or => self.op(op::OR),
+            Xor => self.op(op::XOR),
+            Not => self.op(op::NOT),
+            Shl => self.op(op::SHL),
+            Shr => self.op(op::SHR),
+            LShr => self.op(op::LSHR),
+            PopCnt => self.op(op::POPCNT),
+            RotL => self.op(op::ROTL),
+            RotR => self.op(op::ROTR),
+            ANew => self.op(op::A_NEW),
+            ALoad => self.op(op::A_LOAD),
+            AStore => self.op(op::A_STORE),
+            ALen => self.op(op::A_LEN),
+            APush => self.op(op::A_PUSH),
+            APop => self.op(op::A_POP),
+            MNew => self.op(op::M_NEW),
+            MSet => self.op(op::M_SET),
+            MGet(p) => self.pointer_op(op::M_GET, *p),
+            MDel => self.op(op::M_DEL),
+            MLen => self.op(op::M_LEN),
+            MKeys => self.op(op::M_KEYS),
+        }
+    }
+
+    // An opcode with a single pointer or index operand.
+    fn pointer_op(&mut self, op: u8, p: Pointer) {
+        self.op(op);
+        self.pointer(p);
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8]> {
+        let end = self.pos.checked_add(n).ok_or_else(|| invalid("unexpected end of file"))?;
+        let bytes = self.bytes.get(self.pos..end).ok_or_else(|| invalid("unexpected end of file"))?;
+        self.pos = end;
+
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut v = 0_u64;
+
+        for shift in (0..64).step_by(7) {
+            let b = self.byte()?;
+            v |= ((b & 0x7f) as u64) << shift;
+
+            if b & 0x80 == 0 {
+                return Ok(v);
+            }
+        }
+
+        Err(invalid("varint is too long"))
+    }
+
+    fn int(&mut self) -> Result<isize> {
+        let v = self.varint()?;
+        Ok(((v >> 1) as isize) ^ -((v & 1) as isize))
+    }
+
+    fn pointer(&mut self) -> Result<Pointer> {
+        Ok(self.varint()? as Pointer)
+    }
+
+    fn constant(&mut self) -> Result<Constant> {
+        match self.byte()? {
+            CONST_STR => {
+                let len = self.varint()? as usize;
+                let s = std::str::from_utf8(self.take(len)?).map_err(|_| invalid("string constant is not valid utf-8"))?;
+                Ok(Constant::Str(s.to_string()))
+            }
+            CONST_FLOAT => {
+                let b = self.take(4)?;
+                Ok(Constant::Float(f32::from_le_bytes([b[0], b[1], b[2], b[3]])))
+            }
+            CONST_DOUBLE => {
+                let b = self.take(8)?;
+                Ok(Constant::Double(f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])))
+            }
+            t => Err(invalid(&format!("unknown constant tag {}", t))),
+        }
+    }
+
+    fn string(&mut self, constants: &[Constant]) -> Result<String> {
+        match constants.get(self.varint()? as usize) {
+            Some(Constant::Str(s)) => Ok(s.clone()),
+            _ => Err(invalid("expected a string constant")),
+        }
+    }
+
+    fn float(&mut self, constants: &[Constant]) -> Result<f32> {
+        match constants.get(self.varint()? as usize) {
+            Some(Constant::Float(f)) => Ok(*f),
+            _ => Err(invalid("expected a float constant")),
+        }
+    }
+
+    fn double(&mut self, constants: &[Constant]) -> Result<f64> {
+        match constants.get(self.varint()? as usize) {
+            Some(Constant::Double(d)) => Ok(*d),
+            _ => Err(invalid("expected a double constant")),
+        }
+    }
+
+    fn instruction(&mut self, constants: &[Constant]) -> Result<Instruction> {
+        use Instruction::*;
+
+        Ok(match self.byte()? {
+            op::PUSH_INT => PushInt(self.int()?),
+            op::PUSH_FLT => PushFlt(self.float(constants)?),
+            op::PUSH_STR => PushStr(self.string(constants)?),
+            op::POP => Pop,
+            op::DUP => Dup,
+            op::SWAP => Swap,
+            op::CLR_STK => ClrStk,
+            op::DLC_STK => DlcStk,
+            op::ADD => Add,
+            op::ADD_F => AddF,
+            op::SUB => Sub,
+            op::SUB_F => SubF,
+            op::INCR => Incr,
+            op::DECR => Decr,
+            op::MUL => Mul,
+            op::MUL_F => MulF,
+            op::DIV => Div,
+            op::DIV_F => DivF,
+            op::MOD => Mod,
+            op::MOD_F => ModF,
+            op::MOV => Mov(self.int()?, self.int()?),
+            op::LD => Ld(self.int()?),
+            op::DMP_HASH => DmpHash(self.int()?),
+            op::CLR_HASH => ClrHash,
+            op::DLC_HASH => DlcHash,
+            op::DMP_REG => DmpReg(self.int()?),
+            op::CLR_REG => ClrReg,
+            op::DLC_REG => DlcReg,
+            op::CMP => Cmp(self.pointer()?),
+            op::INT_HAS => IntHas(self.pointer()?),
+            op::STR_HAS => StrHas(self.pointer()?),
+            op::FLT_HAS => FltHas(self.pointer()?),
+            op::JMP => Jmp(self.pointer()?),
+            op::JE => JE(self.pointer()?),
+            op::JFE => JFE(self.pointer()?),
+            op::JNE => JNE(self.pointer()?),
+            op::JFNE => JFNE(self.pointer()?),
+            op::JGT => JGT(self.pointer()?),
+            op::JFGT => JFGT(self.pointer()?),
+            op::JLT => JLT(self.pointer()?),
+            op::JFLT => JFLT(self.pointer()?),
+            op::JGE => JGE(self.pointer()?),
+            op::JFGE => JFGE(self.pointer()?),
+            op::JLE => JLE(self.pointer()?),
+            op::JFLE => JFLE(self.pointer()?),
+            op::GET => Get(self.pointer()?),
+            op::SET => Set(self.pointer()?),
+            op::GET_ARG => GetArg(self.pointer()?),
+            op::SET_ARG => SetArg(self.pointer()?),
+            op::NOOP => Noop,
+            op::PRNT => Prnt,
+            op::PRNT_STR => PrntStr(self.string(constants)?),
+            op::PRNTLN => Prntln,
+            op::PRNT_C => PrntC,
+            op::PRNT_CLN => PrntCln,
+            op::PRNT_STK => PrntStk,
+            op::PRNT_REG => PrntReg,
+            op::RD_LN => RdLn(self.pointer()?),
+            op::RD_INT => RdInt(self.pointer()?),
+            op::RD_FLT => RdFlt(self.pointer()?),
+            op::RD_C => RdC,
+            op::ARGC => Argc,
+            op::ARGV => Argv,
+            op::EXIT => Exit(u8::try_from(self.int()?).map_err(|_| invalid("exit code out of range"))?),
+            op::F_OPEN => FOpen,
+            op::F_READ_LN => FReadLn(self.pointer()?),
+            op::F_READ_ALL => FReadAll,
+            op::F_WRITE => FWrite,
+            op::F_CLOSE => FClose,
+            op::CALL => Call(self.pointer()?),
+            op::NCALL => NCall(self.string(constants)?),
+            op::RET => Ret,
+            op::TRACE_ON => Trace(true),
+            op::TRACE_OFF => Trace(false),
+            op::OVERFLOW_TRAP => Overflow(OverflowMode::Trap),
+            op::OVERFLOW_WRAP => Overflow(OverflowMode::Wrap),
+            op::OVERFLOW_SATURATE => Overflow(OverflowMode::Saturate),
+            op::LENIENT_ON => Lenient(true),
+            op::LENIENT_OFF => Lenient(false),
+            op::PUSH_DBL => PushDbl(self.double(constants)?),
+            op::ADD_D => AddD,
+            op::SUB_D => SubD,
+            op::MUL_D => MulD,
+            op::DIV_D => DivD,
+            op::MOD_D => ModD,
+            op::JDE => JDE(self.pointer()?),
+            op::JDNE => JDNE(self.pointer()?),
+            op::JDGT => JDGT(self.pointer()?),
+            op::JDLT => JDLT(self.pointer()?),
+            op::JDGE => JDGE(self.pointer()?),
+            op::JDLE => JDLE(self.pointer()?),
+            op::ITOF => ItoF,
+            op::FTOI => FtoI,
+            op::ITOD => ItoD,
+            op::DTOI => DtoI,
+            op::FTOD => FtoD,
+            op::DTOF => DtoF,
+            op::ITOS => ItoS,
+            op::STOI => StoI,
+            op::FTOS => FtoS,
+            op::STOF => StoF,
+            op::DTOS => DtoS,
+            op::STOD => StoD,
+            op::STR_CAT => StrCat,
+            op::STR_LEN => StrLen,
+            op::SUB_STR => SubStr,
+            op::CHAR_AT => CharAt,
+            op::STR_FIND => StrFind,
+            op::STR_UP => StrUp,
+            op::STR_LOW => StrLow,
+            op::STR_TRIM => StrTrim,
+            op::STR_SPLIT => StrSplit,
+            op::STR_CMP => StrCmp,
+            op::AND => And,
+            op::OR => Or,
+            op::XOR => Xor,
+            op::NOT => Not,
+            op::SHL => Shl,
+            op::SHR => Shr,
+            op::LSHR => LShr,
+            op::POPCNT => PopCnt,
+            op::ROTL => RotL,
+            op::ROTR => RotR,
+            op::A_NEW => ANew,
+            op::A_LOAD => ALoad,
+            op::A_STORE => AStore,
+            op::A_LEN => ALen,
+            op::A_PUSH => APush,
+            op::A_POP => APop,
+            op::M_NEW => MNew,
+            op::M_SET => MSet,
+            op::M_GET => MGet(self.pointer()?),
+            op::M_DEL => MDel,
+            op::M_LEN => MLen,
+            op::M_KEYS => MKeys,
+            o => return Err(invalid(&format!("unknown opcode {}", o))),
+        })
+    }
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut v: u64) {
+    loop {
+        let b = (v & 0x7f) as u8;
+        v >>= 7;
+
+        if v == 0 {
+            bytes.push(b);
+            return;
+        }
+
+        bytes.push(b | 0x80);
+    }
+}
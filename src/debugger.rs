@@ -0,0 +1,259 @@
+use crate::disasm::listing;
+use crate::instructions::Instruction;
+use crate::interpreter::Vm;
+use crate::lexer::{lex_line, Token};
+use crate::span::Source;
+use crate::stack::{describe, Pointer, StackValue};
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "commands:
+  break <target>             stop before the target runs, a label, a procedure or a line number
+  delete [<target>]          remove a breakpoint, or all of them
+  breaks                     list breakpoints
+  step [n]                   execute n instructions, 1 by default
+  continue                   run until a breakpoint is hit or the program halts
+  where                      show the next instruction
+  stack                      show the stack, bottom first
+  regs                       show the registers
+  frames                     show the call stack, innermost first
+  set <index> <value>        overwrite a stack slot with an int, a float, a double (`1.5d`) or a quoted string
+  setreg <register> <value>  overwrite or define a register
+  restart                    clear all state and start over, breakpoints are kept
+  quit";
+
+// An interactive session over a `Vm`, reading commands from `input` and writing everything except
+// the program's own output to `out`. Breakpoints stop the program before the instruction at the
+// breakpoint runs.
+pub struct Debugger<'a> {
+    vm: Vm<'a>,
+    source: &'a Source,
+    listing: Vec<String>,
+    breakpoints: BTreeSet<Pointer>,
+    faulted: bool,
+    started: bool, // whether anything has run since the start or the last `restart`
+}
+
+impl<'a> Debugger<'a> {
+    pub fn new(vm: Vm<'a>, source: &'a Source) -> Self {
+        Debugger { listing: listing(vm.program()),
+                   vm,
+                   source,
+                   breakpoints: BTreeSet::new(),
+                   faulted: false,
+                   started: false }
+    }
+
+    pub fn vm(&self) -> &Vm<'a> {
+        &self.vm
+    }
+
+    // Runs the session until `quit` or the end of `input`.
+    pub fn run(&mut self, input: &mut dyn BufRead, out: &mut dyn Write) -> io::Result<()> {
+        self.location(out)?;
+
+        loop {
+            write!(out, "(asm) ")?;
+            out.flush()?;
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                writeln!(out)?;
+                return Ok(());
+            }
+
+            let words = match lex_line(1, &line) {
+                Ok(tokens) => tokens.into_iter().map(|t| t.node).collect::<Vec<_>>(),
+                Err(e) => {
+                    writeln!(out, "{}", e)?;
+                    continue;
+                }
+            };
+
+            if !self.command(&words, out)? {
+                return Ok(());
+            }
+        }
+    }
+
+    // Runs one command, returns `false` once the session should end.
+    fn command(&mut self, words: &[Token], out: &mut dyn Write) -> io::Result<bool> {
+        let text = words.iter().map(Token::text).collect::<Vec<_>>();
+
+        match text.as_slice() {
+            [] => {}
+            ["quit" | "q"] => return Ok(false),
+            ["help" | "h"] => writeln!(out, "{}", HELP)?,
+            ["break" | "b", target] => match self.resolve(target) {
+                Some(p) => {
+                    self.breakpoints.insert(p);
+                    writeln!(out, "breakpoint at {}", self.describe_pointer(p))?;
+                }
+                None => writeln!(out, "no label, procedure or line `{}`", target)?,
+            },
+            ["delete" | "d"] => self.breakpoints.clear(),
+            ["delete" | "d", target] => match self.resolve(target) {
+                Some(p) if self.breakpoints.remove(&p) => {}
+                _ => writeln!(out, "no breakpoint at `{}`", target)?,
+            },
+            ["breaks"] => {
+                for p in &self.breakpoints {
+                    writeln!(out, "{}", self.describe_pointer(*p))?;
+                }
+            }
+            ["step" | "s"] => self.step(1, out)?,
+            ["step" | "s", n] => match n.parse() {
+                Ok(n) => self.step(n, out)?,
+                Err(_) => writeln!(out, "invalid count `{}`", n)?,
+            },
+            ["continue" | "c"] => self.resume(out)?,
+            ["where" | "w"] => self.location(out)?,
+            ["stack"] => {
+                for (i, value) in self.vm.stack().values.iter().enumerate() {
+                    writeln!(out, "[{}] {}", i, describe(value))?;
+                }
+            }
+            ["regs"] => self.vm.stack_mut().print_registers(out)?,
+            ["frames"] => self.frames(out)?,
+            ["set", i, _] => match (i.parse::<usize>(), value(&words[2])) {
+                (Ok(i), Some(v)) => match self.vm.stack_mut().get_mut(i) {
+                    Ok(slot) => *slot = v,
+                    Err(e) => writeln!(out, "{}", e)?,
+                },
+                (Err(_), _) => writeln!(out, "invalid index `{}`", i)?,
+                (_, None) => writeln!(out, "invalid value `{}`", text[2])?,
+            },
+            ["setreg", r, _] => match (r.parse::<isize>(), value(&words[2])) {
+                (Ok(r), Some(v)) => self.vm.stack_mut().push_register(r, v),
+                (Err(_), _) => writeln!(out, "invalid register `{}`", r)?,
+                (_, None) => writeln!(out, "invalid value `{}`", text[2])?,
+            },
+            ["restart"] => {
+                self.vm.reset();
+                self.faulted = false;
+                self.started = false;
+                self.location(out)?;
+            }
+            _ => writeln!(out, "unknown command `{}`, try `help`", text.join(" "))?,
+        }
+
+        Ok(true)
+    }
+
+    // A label stops on its `label` line, a procedure on the first instruction of its body and a
+    // line number on the first instruction at or after it.
+    fn resolve(&self, target: &str) -> Option<Pointer> {
+        let program = self.vm.program();
+
+        if let Some(p) = program.labels.get(target) {
+            return Some(*p);
+        }
+
+        if let Some((start, _)) = program.procedures.get(target) {
+            return Some(start + 1);
+        }
+
+        let line = target.parse::<usize>().ok()?;
+        program.instructions.iter().position(|i| i.span.line >= line)
+    }
+
+    fn step(&mut self, n: usize, out: &mut dyn Write) -> io::Result<()> {
+        for _ in 0..n {
+            if !self.advance(out)? {
+                break;
+            }
+        }
+
+        self.location(out)
+    }
+
+    // Runs past a breakpoint the program is stopped at, except on the first instruction before
+    // anything has run, where the breakpoint is hit like any other.
+    fn resume(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        if self.started || !self.breakpoints.contains(&self.vm.pointer()) {
+            while self.advance(out)? {
+                if self.breakpoints.contains(&self.vm.pointer()) {
+                    break;
+                }
+            }
+        }
+
+        self.started = true;
+        self.location(out)
+    }
+
+    // Executes one instruction, returns `false` if the program can't go any further.
+    fn advance(&mut self, out: &mut dyn Write) -> io::Result<bool> {
+        if self.faulted || self.vm.is_halted() {
+            return Ok(false);
+        }
+
+        self.started = true;
+
+        // The program's output is flushed first so it shows up before anything the debugger writes.
+        match self.vm.step().and_then(|running| self.vm.flush().map(|()| running)) {
+            Ok(running) => Ok(running),
+            Err(e) => {
+                writeln!(out, "{}", e.render(self.source))?;
+                self.faulted = true;
+                Ok(false)
+            }
+        }
+    }
+
+    fn location(&self, out: &mut dyn Write) -> io::Result<()> {
+        if self.faulted {
+            writeln!(out, "stopped on an error, `restart` to run the program again")
+        } else if let Some(code) = self.vm.exit_code() {
+            writeln!(out, "program exited with code {}", code)
+        } else if self.vm.is_halted() {
+            writeln!(out, "program halted")
+        } else {
+            writeln!(out, "{}", self.describe_pointer(self.vm.pointer()))
+        }
+    }
+
+    // `file:line:col  instruction`
+    fn describe_pointer(&self, p: Pointer) -> String {
+        let span = self.vm.program().instructions[p].span;
+
+        format!("{}  {}", self.source.location(span), self.listing[p])
+    }
+
+    fn frames(&self, out: &mut dyn Write) -> io::Result<()> {
+        let program = self.vm.program();
+
+        for (depth, frame) in self.vm.call_stack().iter().rev().enumerate() {
+            let call = &program.instructions[frame.ip - 1];
+            let name = match call.node {
+                Instruction::Call(p) => match p.checked_sub(1).and_then(|p| self.listing.get(p)) {
+                    Some(line) => line.trim_start_matches("proc "),
+                    None => "?",
+                },
+                _ => "?",
+            };
+
+            writeln!(out,
+                     "#{} {} called from {}, locals from [{}]",
+                     depth,
+                     name,
+                     self.source.location(call.span),
+                     frame.stack_offset)?;
+        }
+
+        writeln!(out, "#{} top level", self.vm.call_stack().len())
+    }
+}
+
+// Reads a `set` value, an integer, a float, a double with a `d` suffix or a quoted string. Floats
+// and doubles must be finite, `nan`, `inf` and literals too large for the type are refused.
+fn value(token: &Token) -> Option<StackValue> {
+    match token {
+        Token::Str(s) => Some(StackValue::Str(s.as_str().into())),
+        Token::Word(w) if w.ends_with('d') => w[..w.len() - 1].parse::<f64>().ok().filter(|v| v.is_finite()).map(StackValue::Double),
+        Token::Word(w) => match w.parse() {
+            Ok(v) => Some(StackValue::Int(v)),
+            Err(_) => w.parse::<f32>().ok().filter(|v| v.is_finite()).map(StackValue::Float),
+        },
+    }
+}
@@ -0,0 +1,128 @@
+use crate::instructions::Instruction;
+use crate::program::Program;
+use crate::stack::Pointer;
+use std::collections::BTreeMap;
+
+// Column the instruction index comments are aligned to.
+const INDEX_COLUMN: usize = 40;
+
+// Turns a program back into assembly. `proc`, `label` and `end` lines are restored from the
+// resolved pointers, using the original names when the program was parsed from source and
+// synthesized ones (`proc0003`, `L0012`) when it was loaded from bytecode. Every line ends with
+// the index of its instruction as a comment, so the output can be assembled again as is.
+pub fn disassemble(program: &Program) -> String {
+    let names = Names::new(program);
+    let lines = listing_with(program, &names);
+    let mut out = format!("; {}\n", program.name);
+    let mut depth = 0;
+
+    for (i, text) in lines.iter().enumerate() {
+        if names.is_proc_end(i) {
+            depth -= 1;
+        }
+
+        let line = format!("{}{}", "    ".repeat(depth), text);
+        out.push_str(&format!("{:<width$}; {:04}\n", line, i, width = INDEX_COLUMN));
+
+        if names.procs.contains_key(&i) {
+            depth += 1;
+        }
+    }
+
+    // A jump past the last instruction, only possible in bytecode where a `proc` at the end of
+    // the program is never called and so reads as a plain `jmp`.
+    if let Some(label) = names.labels.get(&program.instructions.len()) {
+        out.push_str(&format!("label {}\n", label));
+    }
+
+    out
+}
+
+// The assembly text of each instruction, without indentation or index comments.
+pub fn listing(program: &Program) -> Vec<String> {
+    listing_with(program, &Names::new(program))
+}
+
+fn listing_with(program: &Program, names: &Names) -> Vec<String> {
+    program.instructions.iter().enumerate().map(|(i, instruction)| names.text(i, &instruction.node)).collect()
+}
+
+struct Names {
+    procs: BTreeMap<Pointer, (String, Pointer)>, // proc line -> (name, index after `end`)
+    labels: BTreeMap<Pointer, String>,
+}
+
+impl Names {
+    fn new(program: &Program) -> Self {
+        let instructions = &program.instructions;
+
+        let mut procs = program.procedures
+                               .iter()
+                               .map(|(name, (start, end))| (*start, (name.clone(), *end)))
+                               .collect::<BTreeMap<_, _>>();
+        let mut labels = program.labels.iter().map(|(name, i)| (*i, name.clone())).collect::<BTreeMap<_, _>>();
+
+        if program.procedures.is_empty() {
+            // A call lands just past a `proc` line, which is a jump over the body to the line after `end`.
+            for instruction in instructions {
+                if let Instruction::Call(p) = instruction.node {
+                    if let Some(Instruction::Jmp(end)) = p.checked_sub(1).and_then(|s| instructions.get(s)).map(|i| &i.node) {
+                        procs.insert(p - 1, (format!("proc{:04}", p - 1), *end));
+                    }
+                }
+            }
+        }
+
+        for (i, instruction) in instructions.iter().enumerate() {
+            match (&instruction.node, instruction.node.target()) {
+                (Instruction::Call(_), _) => {}
+                (Instruction::Jmp(_), _) if procs.contains_key(&i) => {}
+                (_, Some(p)) => {
+                    labels.entry(p).or_insert_with(|| format!("L{:04}", p));
+                }
+                _ => {}
+            }
+        }
+
+        Names { procs, labels }
+    }
+
+    fn is_proc_end(&self, i: Pointer) -> bool {
+        self.procs.values().any(|(_, end)| *end == i + 1)
+    }
+
+    fn label(&self, p: Pointer) -> String {
+        self.labels.get(&p).cloned().unwrap_or_else(|| format!("L{:04}", p))
+    }
+
+    fn proc(&self, p: Pointer) -> String {
+        self.procs.get(&p).map(|(name, _)| name.clone()).unwrap_or_else(|| format!("proc{:04}", p))
+    }
+
+    fn text(&self, i: Pointer, instruction: &Instruction) -> String {
+        use Instruction::*;
+
+        let m = instruction.mnemonic();
+
+        match instruction {
+            Noop if self.is_proc_end(i) => "end".to_string(),
+            Noop => format!("label {}", self.label(i)),
+            Jmp(_) if self.procs.contains_key(&i) => format!("proc {}", self.proc(i)),
+            Call(p) => format!("{} {}", m, self.proc(p.saturating_sub(1))),
+            PushInt(d) | Ld(d) | DmpHash(d) | DmpReg(d) => format!("{} {}", m, d),
+            Exit(d) => format!("{} {}", m, d),
+            PushFlt(d) => format!("{} {:?}", m, d),
+            PushDbl(d) => format!("{} {:?}", m, d),
+            PushStr(d) | PrntStr(d) => format!("{} {:?}", m, d),
+            NCall(d) => format!("{} {}", m, d),
+            Mov(d, p) => format!("{} {} {}", m, d, p),
+            Get(p) | Set(p) | GetArg(p) | SetArg(p) => format!("{} {}", m, p),
+            Trace(on) | Lenient(on) => format!("{} {}", m, if *on { "on" } else { "off" }),
+            Overflow(mode) => format!("{} {}", m, mode.name()),
+            _ => match instruction.target() {
+                Some(p) => format!("{} {}", m, self.label(p)),
+                None => m.to_string(),
+            },
+        }
+    }
+}
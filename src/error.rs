@@ -0,0 +1,135 @@
+use crate::span::{Source, Span};
+use std::fmt;
+use std::ops::Deref;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    InvalidInstruction(String),
+    InvalidOperand(String, &'static str), // operand, expected type
+    UnknownLabel(String),
+    UnknownProcedure(String),
+    UnterminatedProcedure(String),
+    StackUnderflow,
+    InvalidIndex(i128), // wide enough for both `isize` operands and `usize` indices
+    MissingCallFrame,
+    UnknownNative(String),
+    TypeMismatch(&'static str, &'static str),      // expected, found
+    OperandMismatch(&'static str, String, String), // instruction, expected, found
+    Native(String),
+    Io(std::io::Error),
+    UnterminatedString,
+    InvalidEscape(String),
+    InvalidBytecode(String),
+    DivisionByZero(&'static str),                          // instruction
+    Overflow(&'static str),                                // instruction
+    InvalidConversion(&'static str, String, &'static str), // instruction, value, target type
+    OutOfBounds(&'static str, isize, usize),               // instruction, index, length
+    InvalidShift(&'static str, isize),                     // instruction, amount
+    Empty(&'static str),                                   // instruction
+    InvalidSize(&'static str, isize),                      // instruction, size
+    InvalidLength(&'static str, isize),                    // instruction, length
+    EmptySeparator(&'static str),                          // instruction
+    AllocationFailed(&'static str, usize),                 // instruction, size
+    FileDenied(String, &'static str),                      // path, reason
+    FileIo(String, std::io::Error),                        // path, error
+    InvalidFileMode(String),
+    InvalidFile(&'static str, &'static str), // instruction, what the file is
+}
+
+// Boxed so a `Result` stays a word or two wide, which keeps every `?` in the interpreter loop cheap.
+// `kind` and `span` are reached through `Deref`.
+#[derive(Debug)]
+pub struct Error(Box<ErrorInfo>);
+
+#[derive(Debug)]
+pub struct ErrorInfo {
+    pub kind: ErrorKind,
+    pub span: Option<Span>,
+}
+
+impl Deref for Error {
+    type Target = ErrorInfo;
+
+    fn deref(&self) -> &ErrorInfo {
+        &self.0
+    }
+}
+
+impl Error {
+    // Attaches a location to the error, keeping the innermost one if it already has a span.
+    pub fn at(mut self, span: Span) -> Self {
+        self.0.span.get_or_insert(span);
+        self
+    }
+
+    // Renders the error as a `file:line:col` diagnostic with a caret-underlined excerpt.
+    pub fn render(&self, source: &Source) -> String {
+        match self.span {
+            Some(span) if source.text.is_empty() => format!("error: {}\n --> {}", self.kind, source.location(span)),
+            Some(span) => format!("error: {}\n --> {}\n{}", self.kind, source.location(span), source.excerpt(span)),
+            None => format!("error: {}\n --> {}", self.kind, source.name),
+        }
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Error(Box::new(ErrorInfo { kind, span: None }))
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        ErrorKind::Io(e).into()
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::InvalidInstruction(s) => write!(f, "invalid instruction: `{}`", s),
+            ErrorKind::InvalidOperand(s, t) => write!(f, "invalid operand: `{}` is not a valid {}", s, t),
+            ErrorKind::UnknownLabel(s) => write!(f, "unknown label: `{}`", s),
+            ErrorKind::UnknownProcedure(s) => write!(f, "unknown procedure: `{}`", s),
+            ErrorKind::UnterminatedProcedure(s) => write!(f, "procedure `{}` is missing a matching `end`", s),
+            ErrorKind::StackUnderflow => write!(f, "stack underflow"),
+            ErrorKind::InvalidIndex(i) => write!(f, "invalid stack index: {}", i),
+            ErrorKind::MissingCallFrame => write!(f, "no call frame to return to"),
+            ErrorKind::UnknownNative(s) => write!(f, "unknown native function: `{}`", s),
+            ErrorKind::TypeMismatch(e, t) => write!(f, "type mismatch: expected {}, found {}", e, t),
+            ErrorKind::OperandMismatch(i, e, t) => write!(f, "type mismatch in `{}`: expected {}, found {}", i, e, t),
+            ErrorKind::Native(s) => write!(f, "native function failed: {}", s),
+            ErrorKind::Io(e) => write!(f, "io error: {}", e),
+            ErrorKind::UnterminatedString => write!(f, "unterminated string literal"),
+            ErrorKind::InvalidEscape(s) => write!(f, "invalid escape sequence: `{}`", s),
+            ErrorKind::InvalidBytecode(s) => write!(f, "invalid bytecode: {}", s),
+            ErrorKind::DivisionByZero(s) => write!(f, "division by zero in `{}`", s),
+            ErrorKind::Overflow(s) => write!(f, "integer overflow in `{}`", s),
+            ErrorKind::InvalidConversion(i, v, t) => write!(f, "`{}` cannot convert `{}` to {}", i, v, t),
+            ErrorKind::InvalidShift(i, n) => write!(f, "shift amount {} is out of range in `{}`, expected 0 to {}", n, i, isize::BITS - 1),
+            ErrorKind::Empty(i) => write!(f, "`{}` on an empty array", i),
+            ErrorKind::InvalidSize(i, n) => write!(f, "invalid size {} in `{}`, expected 0 or more", n, i),
+            ErrorKind::InvalidLength(i, n) => write!(f, "invalid length {} in `{}`, expected 0 or more", n, i),
+            ErrorKind::EmptySeparator(i) => write!(f, "empty separator in `{}`", i),
+            ErrorKind::AllocationFailed(i, n) => write!(f, "`{}` cannot allocate {} items", i, n),
+            ErrorKind::OutOfBounds(i, n, len) => write!(f, "index {} is out of bounds for length {} in `{}`", n, len, i),
+            ErrorKind::FileDenied(p, r) => write!(f, "cannot open `{}`: {}", p, r),
+            ErrorKind::FileIo(p, e) => write!(f, "cannot open `{}`: {}", p, e),
+            ErrorKind::InvalidFileMode(m) => write!(f, "invalid file mode `{}`, expected r, w or a", m),
+            ErrorKind::InvalidFile(i, what) => write!(f, "`{}` on {}", i, what),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.span {
+            Some(span) => write!(f, "{}: {}", span, self.kind),
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
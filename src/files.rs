@@ -0,0 +1,217 @@
+use crate::error::{ErrorKind, Result};
+use std::cell::RefCell;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+// Which files `fopen` may touch. Nothing is allowed by default, a script only gets access to the
+// directories the host allows, and only to read them in read-only mode. Paths are resolved before
+// they're checked, so `..` and symlinks can't be used to get out of an allowed directory.
+#[derive(Debug, Clone, Default)]
+pub struct FilePolicy {
+    dirs: Vec<PathBuf>,
+    read_only: bool,
+}
+
+impl FilePolicy {
+    // No file access at all, the default.
+    pub fn disabled() -> Self {
+        FilePolicy::default()
+    }
+
+    // Allows everything under `dir`, which has to exist.
+    pub fn allow(&mut self, dir: impl AsRef<Path>) -> io::Result<()> {
+        self.dirs.push(dir.as_ref().canonicalize()?);
+        Ok(())
+    }
+
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.dirs.is_empty()
+    }
+
+    // Opens `path` for reading (`r`), writing (`w`) or appending (`a`) if the policy allows it.
+    pub fn open(&self, path: &str, mode: &str) -> Result<Handle> {
+        let denied = |reason| ErrorKind::FileDenied(path.to_string(), reason);
+        let failed = |e| ErrorKind::FileIo(path.to_string(), e);
+
+        if self.is_disabled() {
+            return Err(denied("file access is disabled").into());
+        }
+        if self.read_only && mode != "r" {
+            return Err(denied("file access is read-only").into());
+        }
+
+        // A file that is about to be created doesn't exist yet, so its directory is resolved instead.
+        let resolved = match Path::new(path).canonicalize() {
+            Ok(p) => p,
+            Err(e) if mode == "r" => return Err(failed(e).into()),
+            Err(_) => {
+                let path = Path::new(path);
+
+                // A dangling symlink would otherwise be followed by the create below, wherever it points.
+                if path.symlink_metadata().is_ok_and(|m| m.file_type().is_symlink()) {
+                    return Err(denied("it is a symlink to a file that doesn't exist").into());
+                }
+
+                let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+                    return Err(denied("it is not a file").into());
+                };
+                let parent = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+
+                parent.canonicalize().map_err(failed)?.join(name)
+            }
+        };
+
+        if !self.is_allowed(&resolved) {
+            return Err(denied("it is outside the allowed directories").into());
+        }
+
+        // `w` truncates only once the file is known to be the right one.
+        let file = match mode {
+            "r" => File::open(&resolved),
+            "w" => OpenOptions::new().write(true).create(true).truncate(false).open(&resolved),
+            "a" => OpenOptions::new().append(true).create(true).open(&resolved),
+            _ => return Err(ErrorKind::InvalidFileMode(mode.to_string()).into()),
+        };
+        let file = file.map_err(failed)?;
+
+        // The path could have been swapped for a symlink since it was checked, so the file that was
+        // actually opened has to still be the one at the resolved path.
+        if !self.is_same_file(&file, &resolved) {
+            return Err(denied("it changed while it was being opened").into());
+        }
+        if mode == "w" {
+            file.set_len(0).map_err(failed)?;
+        }
+
+        let state = match mode {
+            "r" => State::Read(BufReader::new(file)),
+            _ => State::Write(BufWriter::new(file)),
+        };
+
+        Ok(Handle(Rc::new(RefCell::new(OpenFile { path: path.into(), state }))))
+    }
+
+    fn is_allowed(&self, resolved: &Path) -> bool {
+        self.dirs.iter().any(|d| resolved.starts_with(d))
+    }
+
+    fn is_same_file(&self, file: &File, resolved: &Path) -> bool {
+        let Ok(real) = resolved.canonicalize() else {
+            return false;
+        };
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            match (file.metadata(), real.metadata()) {
+                (Ok(a), Ok(b)) if a.dev() == b.dev() && a.ino() == b.ino() => {}
+                _ => return false,
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = file;
+
+        real == resolved && self.is_allowed(&real)
+    }
+}
+
+// A file opened by `fopen`. Copies of a handle share the file, closing one closes them all.
+#[derive(Clone)]
+pub struct Handle(Rc<RefCell<OpenFile>>);
+
+struct OpenFile {
+    path: Rc<str>,
+    state: State,
+}
+
+enum State {
+    Read(BufReader<File>),
+    Write(BufWriter<File>),
+    Closed,
+}
+
+impl Handle {
+    // The next line without its line ending, or `None` at the end of the file.
+    pub fn read_line(&self, instruction: &'static str) -> Result<Option<String>> {
+        let mut file = self.0.borrow_mut();
+        let reader = file.reader(instruction)?;
+
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let len = line.trim_end_matches(['\n', '\r']).len();
+        line.truncate(len);
+
+        Ok(Some(line))
+    }
+
+    // Everything from the current position to the end of the file.
+    pub fn read_all(&self, instruction: &'static str) -> Result<String> {
+        let mut file = self.0.borrow_mut();
+        let mut text = String::new();
+        file.reader(instruction)?.read_to_string(&mut text)?;
+
+        Ok(text)
+    }
+
+    pub fn write(&self, instruction: &'static str, text: &str) -> Result<()> {
+        let mut file = self.0.borrow_mut();
+
+        match &mut file.state {
+            State::Write(w) => Ok(w.write_all(text.as_bytes())?),
+            State::Read(_) => Err(ErrorKind::InvalidFile(instruction, "a file opened for reading").into()),
+            State::Closed => Err(ErrorKind::InvalidFile(instruction, "a closed file").into()),
+        }
+    }
+
+    // Flushes what was written and closes the file, closing it again does nothing.
+    pub fn close(&self) -> Result<()> {
+        let mut file = self.0.borrow_mut();
+
+        if let State::Write(w) = &mut file.state {
+            w.flush()?;
+        }
+        file.state = State::Closed;
+
+        Ok(())
+    }
+}
+
+impl OpenFile {
+    fn reader(&mut self, instruction: &'static str) -> Result<&mut BufReader<File>> {
+        match &mut self.state {
+            State::Read(r) => Ok(r),
+            State::Write(_) => Err(ErrorKind::InvalidFile(instruction, "a file opened for writing").into()),
+            State::Closed => Err(ErrorKind::InvalidFile(instruction, "a closed file").into()),
+        }
+    }
+}
+
+// Two handles are equal when they refer to the same `fopen`.
+impl PartialEq for Handle {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Display for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<file {:?}>", self.0.borrow().path)
+    }
+}
+
+impl fmt::Debug for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handle({:?})", self.0.borrow().path)
+    }
+}
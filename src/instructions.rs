@@ -1,3 +1,4 @@
+use crate::interpreter::OverflowMode;
 use crate::stack::Pointer;
 
 #[non_exhaustive]
@@ -7,19 +8,159 @@ impl Type {
     pub const INT: &str = "int";
     pub const STRING: &str = "str";
     pub const FLOAT: &str = "flt";
+    pub const DOUBLE: &str = "dbl";
+    pub const ARRAY: &str = "arr";
+    pub const MAP: &str = "map";
+    pub const FILE: &str = "file";
 }
 
-// TODO: Possibly add some instructions to assist with debugging?
-// These would sort of act like environment variables.
-//
-// I would probably want to add a distinct marker for these variables,
-// Possibly using the #, @, &, or $ symbol, although I would want to determine
-// use cases for the other symbols first.
-//
-// Debug variables would not change any instruction, but would print what the instruction is doing
-// I would want to pretty-print the value in some way so it doesn't feel like a gigantic dump of print
-// statements when running your program.
-//
+// Every mnemonic the parser accepts, as the canonical name followed by its aliases.
+// Lookups are case-insensitive, so `PushStr`, `PUSHSTR` and `pstr` all resolve to `pushstr`.
+pub const MNEMONICS: &[(&str, &[&str])] = &[("pushint", &["pint", "push"]),
+                                            ("pushflt", &["pflt", "pushfloat"]),
+                                            ("pushdbl", &["pdbl", "pushdouble"]),
+                                            ("pushstr", &["pstr"]),
+                                            ("pop", &[]),
+                                            ("dup", &[]),
+                                            ("swap", &[]),
+                                            ("clrstk", &[]),
+                                            ("dlcstk", &[]),
+                                            ("add", &[]),
+                                            ("addf", &[]),
+                                            ("sub", &[]),
+                                            ("subf", &[]),
+                                            ("mul", &[]),
+                                            ("mulf", &[]),
+                                            ("div", &[]),
+                                            ("divf", &[]),
+                                            ("mod", &[]),
+                                            ("modf", &[]),
+                                            ("addd", &[]),
+                                            ("subd", &[]),
+                                            ("muld", &[]),
+                                            ("divd", &[]),
+                                            ("modd", &[]),
+                                            ("itof", &[]),
+                                            ("ftoi", &[]),
+                                            ("itod", &[]),
+                                            ("dtoi", &[]),
+                                            ("ftod", &[]),
+                                            ("dtof", &[]),
+                                            ("itos", &[]),
+                                            ("stoi", &[]),
+                                            ("ftos", &[]),
+                                            ("stof", &[]),
+                                            ("dtos", &[]),
+                                            ("stod", &[]),
+                                            ("strcat", &[]),
+                                            ("strlen", &[]),
+                                            ("substr", &[]),
+                                            ("charat", &[]),
+                                            ("strfind", &[]),
+                                            ("strup", &[]),
+                                            ("strlow", &[]),
+                                            ("strtrim", &[]),
+                                            ("strsplit", &[]),
+                                            ("strcmp", &[]),
+                                            ("anew", &[]),
+                                            ("aload", &[]),
+                                            ("astore", &[]),
+                                            ("alen", &[]),
+                                            ("apush", &[]),
+                                            ("apop", &[]),
+                                            ("mnew", &[]),
+                                            ("mset", &[]),
+                                            ("mget", &[]),
+                                            ("mdel", &[]),
+                                            ("mlen", &[]),
+                                            ("mkeys", &[]),
+                                            ("incr", &[]),
+                                            ("decr", &[]),
+                                            ("and", &[]),
+                                            ("or", &[]),
+                                            ("xor", &[]),
+                                            ("not", &[]),
+                                            ("shl", &[]),
+                                            ("shr", &[]),
+                                            ("lshr", &[]),
+                                            ("popcnt", &[]),
+                                            ("rotl", &[]),
+                                            ("rotr", &[]),
+                                            ("mov", &[]),
+                                            ("ld", &[]),
+                                            ("dmphash", &[]),
+                                            ("clrhash", &[]),
+                                            ("dlchash", &[]),
+                                            ("dmpreg", &[]),
+                                            ("clrreg", &[]),
+                                            ("dlcreg", &[]),
+                                            ("jmp", &["jump"]),
+                                            ("cmp", &[]),
+                                            ("inthas", &[]),
+                                            ("strhas", &["incl"]),
+                                            ("flthas", &[]),
+                                            ("je", &[]),
+                                            ("jfe", &[]),
+                                            ("jne", &[]),
+                                            ("jfne", &[]),
+                                            ("jgt", &[]),
+                                            ("jfgt", &[]),
+                                            ("jlt", &[]),
+                                            ("jflt", &[]),
+                                            ("jge", &[]),
+                                            ("jfge", &[]),
+                                            ("jle", &[]),
+                                            ("jfle", &[]),
+                                            ("jde", &[]),
+                                            ("jdne", &[]),
+                                            ("jdgt", &[]),
+                                            ("jdlt", &[]),
+                                            ("jdge", &[]),
+                                            ("jdle", &[]),
+                                            ("get", &[]),
+                                            ("set", &[]),
+                                            ("getarg", &[]),
+                                            ("setarg", &[]),
+                                            ("prnt", &["print"]),
+                                            ("prntstr", &["printstr"]),
+                                            ("prntln", &["println"]),
+                                            ("prntc", &["printc"]),
+                                            ("prntcln", &["printcln"]),
+                                            ("prntstk", &["printstack"]),
+                                            ("prntreg", &["printregisters"]),
+                                            ("rdln", &["readln"]),
+                                            ("rdint", &["readint"]),
+                                            ("rdflt", &["readflt"]),
+                                            ("rdc", &["readc"]),
+                                            ("argc", &[]),
+                                            ("argv", &[]),
+                                            ("exit", &[]),
+                                            ("fopen", &[]),
+                                            ("freadln", &[]),
+                                            ("freadall", &[]),
+                                            ("fwrite", &[]),
+                                            ("fclose", &[]),
+                                            ("call", &[]),
+                                            ("ncall", &[]),
+                                            ("ret", &[]),
+                                            ("proc", &[]),
+                                            ("end", &[]),
+                                            ("label", &[]),
+                                            ("#trace", &[]),
+                                            ("#overflow", &[]),
+                                            ("#lenient", &[])];
+
+// Resolves a mnemonic or one of its aliases to the canonical name.
+pub fn canonical(mnemonic: &str) -> Option<&'static str> {
+    MNEMONICS.iter()
+             .find(|(name, aliases)| name.eq_ignore_ascii_case(mnemonic) || aliases.iter().any(|a| a.eq_ignore_ascii_case(mnemonic)))
+             .map(|(name, _)| *name)
+}
+
+// Directives are marked with `#`. They don't touch the stack, but change how the instructions
+// after them run, `#trace on` prints what every instruction does until `#trace off` and
+// `#overflow wrap` makes integer arithmetic wrap around instead of failing.
+// `#lenient on` makes instructions given operands of the wrong type drop them instead of failing.
 //
 // TODO: Consider renaming instructions to fit a more concise convention, I don't know exactly how I want
 // to do this just yet, but the amount of instructions is going to increase and get more "cluttered"
@@ -36,6 +177,7 @@ impl Type {
 pub enum Instruction {
     PushInt(isize),
     PushFlt(f32),
+    PushDbl(f64),
     PushStr(String),
     Pop,
     Dup,
@@ -48,12 +190,61 @@ pub enum Instruction {
     SubF,   // float
     Incr,   // int
     Decr,   // int
+    And,    // int
+    Or,     // int
+    Xor,    // int
+    Not,    // int
+    Shl,    // int
+    Shr,    // int, arithmetic
+    LShr,   // int, logical
+    PopCnt, // int
+    RotL,   // int
+    RotR,   // int
     Mul,    // int
     MulF,   // float
     Div,    // int
     DivF,   // float
     Mod,    // int
     ModF,   // Float
+    AddD,   // double
+    SubD,   // double
+    MulD,   // double
+    DivD,   // double
+    ModD,   // double
+    ItoF,   // int -> float
+    FtoI,   // float -> int
+    ItoD,   // int -> double
+    DtoI,   // double -> int
+    FtoD,   // float -> double
+    DtoF,   // double -> float
+    ItoS,   // int -> string
+    StoI,   // string -> int
+    FtoS,   // float -> string
+    StoF,   // string -> float
+    DtoS,   // double -> string
+    StoD,   // string -> double
+    StrCat,
+    StrLen,
+    SubStr,
+    CharAt,
+    StrFind,
+    StrUp,
+    StrLow,
+    StrTrim,
+    StrSplit,
+    StrCmp,
+    ANew,
+    ALoad,
+    AStore,
+    ALen,
+    APush,
+    APop,
+    MNew,
+    MSet,
+    MGet(Pointer), // jumps when the key is missing
+    MDel,
+    MLen,
+    MKeys,
     Mov(isize, isize),
     Ld(isize),
     DmpHash(isize),
@@ -79,6 +270,12 @@ pub enum Instruction {
     JFGE(Pointer), // float
     JLE(Pointer),  // int
     JFLE(Pointer), // float
+    JDE(Pointer),  // double
+    JDNE(Pointer), // double
+    JDGT(Pointer), // double
+    JDLT(Pointer), // double
+    JDGE(Pointer), // double
+    JDLE(Pointer), // double
     Get(Pointer),
     Set(Pointer),
     GetArg(Pointer),
@@ -91,6 +288,174 @@ pub enum Instruction {
     PrntCln,
     PrntStk,
     PrntReg,
+    RdLn(Pointer),  // jumps at the end of the input
+    RdInt(Pointer), // jumps at the end of the input or if the line isn't an int
+    RdFlt(Pointer),
+    RdC,
+    Argc,
+    Argv,
+    Exit(u8),
+    FOpen,
+    FReadLn(Pointer), // jumps at the end of the file
+    FReadAll,
+    FWrite,
+    FClose,
     Call(Pointer),
+    NCall(String),
     Ret,
+    Trace(bool), // #trace on | off
+    Overflow(OverflowMode),
+    Lenient(bool), // #lenient on | off
+}
+
+impl Instruction {
+    // The canonical mnemonic, `noop` is never written by hand and stands for `label` and `end` lines.
+    pub fn mnemonic(&self) -> &'static str {
+        use Instruction::*;
+
+        match self {
+            PushInt(..) => "pushint",
+            PushFlt(..) => "pushflt",
+            PushDbl(..) => "pushdbl",
+            PushStr(..) => "pushstr",
+            Pop => "pop",
+            Dup => "dup",
+            Swap => "swap",
+            ClrStk => "clrstk",
+            DlcStk => "dlcstk",
+            Add => "add",
+            AddF => "addf",
+            Sub => "sub",
+            SubF => "subf",
+            Incr => "incr",
+            Decr => "decr",
+            And => "and",
+            Or => "or",
+            Xor => "xor",
+            Not => "not",
+            Shl => "shl",
+            Shr => "shr",
+            LShr => "lshr",
+            PopCnt => "popcnt",
+            RotL => "rotl",
+            RotR => "rotr",
+            Mul => "mul",
+            MulF => "mulf",
+            Div => "div",
+            DivF => "divf",
+            Mod => "mod",
+            ModF => "modf",
+            AddD => "addd",
+            SubD => "subd",
+            MulD => "muld",
+            DivD => "divd",
+            ModD => "modd",
+            ItoF => "itof",
+            FtoI => "ftoi",
+            ItoD => "itod",
+            DtoI => "dtoi",
+            FtoD => "ftod",
+            DtoF => "dtof",
+            ItoS => "itos",
+            StoI => "stoi",
+            FtoS => "ftos",
+            StoF => "stof",
+            DtoS => "dtos",
+            StoD => "stod",
+            StrCat => "strcat",
+            StrLen => "strlen",
+            SubStr => "substr",
+            CharAt => "charat",
+            StrFind => "strfind",
+            StrUp => "strup",
+            StrLow => "strlow",
+            StrTrim => "strtrim",
+            StrSplit => "strsplit",
+            StrCmp => "strcmp",
+            ANew => "anew",
+            ALoad => "aload",
+            AStore => "astore",
+            ALen => "alen",
+            APush => "apush",
+            APop => "apop",
+            MNew => "mnew",
+            MSet => "mset",
+            MGet(..) => "mget",
+            MDel => "mdel",
+            MLen => "mlen",
+            MKeys => "mkeys",
+            Mov(..) => "mov",
+            Ld(..) => "ld",
+            DmpHash(..) => "dmphash",
+            ClrHash => "clrhash",
+            DlcHash => "dlchash",
+            DmpReg(..) => "dmpreg",
+            ClrReg => "clrreg",
+            DlcReg => "dlcreg",
+            Cmp(..) => "cmp",
+            IntHas(..) => "inthas",
+            StrHas(..) => "strhas",
+            FltHas(..) => "flthas",
+            Jmp(..) => "jmp",
+            JE(..) => "je",
+            JFE(..) => "jfe",
+            JNE(..) => "jne",
+            JFNE(..) => "jfne",
+            JGT(..) => "jgt",
+            JFGT(..) => "jfgt",
+            JLT(..) => "jlt",
+            JFLT(..) => "jflt",
+            JGE(..) => "jge",
+            JFGE(..) => "jfge",
+            JLE(..) => "jle",
+            JFLE(..) => "jfle",
+            JDE(..) => "jde",
+            JDNE(..) => "jdne",
+            JDGT(..) => "jdgt",
+            JDLT(..) => "jdlt",
+            JDGE(..) => "jdge",
+            JDLE(..) => "jdle",
+            Get(..) => "get",
+            Set(..) => "set",
+            GetArg(..) => "getarg",
+            SetArg(..) => "setarg",
+            Noop => "noop",
+            Prnt => "prnt",
+            PrntStr(..) => "prntstr",
+            Prntln => "prntln",
+            PrntC => "prntc",
+            PrntCln => "prntcln",
+            PrntStk => "prntstk",
+            PrntReg => "prntreg",
+            RdLn(..) => "rdln",
+            RdInt(..) => "rdint",
+            RdFlt(..) => "rdflt",
+            RdC => "rdc",
+            Argc => "argc",
+            Argv => "argv",
+            Exit(..) => "exit",
+            FOpen => "fopen",
+            FReadLn(..) => "freadln",
+            FReadAll => "freadall",
+            FWrite => "fwrite",
+            FClose => "fclose",
+            Call(..) => "call",
+            NCall(..) => "ncall",
+            Ret => "ret",
+            Trace(..) => "#trace",
+            Overflow(..) => "#overflow",
+            Lenient(..) => "#lenient",
+        }
+    }
+
+    // The instruction a jump, branch or call can transfer control to.
+    pub fn target(&self) -> Option<Pointer> {
+        use Instruction::*;
+
+        match self {
+            Cmp(p) | IntHas(p) | StrHas(p) | FltHas(p) | Jmp(p) | JE(p) | JFE(p) | JNE(p) | JFNE(p) | JGT(p) | JFGT(p) | JLT(p) | JFLT(p) | JGE(p) | JFGE(p) | JLE(p) | JFLE(p)
+            | JDE(p) | JDNE(p) | JDGT(p) | JDLT(p) | JDGE(p) | JDLE(p) | MGet(p) | RdLn(p) | RdInt(p) | RdFlt(p) | FReadLn(p) | Call(p) => Some(*p),
+            _ => None,
+        }
+    }
 }
@@ -1,497 +1,1122 @@
-use crate::instructions::Instruction;
+use crate::disasm::listing;
+use crate::error::{Error, ErrorKind, Result};
+use crate::files::FilePolicy;
+use crate::instructions::{Instruction, Type};
+use crate::parser::parse;
+use crate::program::Program;
+use crate::span::{Source, Span, Spanned};
 use crate::stack::*;
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, BufReader, Write};
+use std::rc::Rc;
+use std::str::FromStr;
 
-pub fn compile(buffer: String) {
-    let line_splits = buffer.split('\n')
-                            .map(|s| s.split_whitespace().collect::<Vec<_>>())
-                            .filter(|s| !matches!(s.as_slice(), [] | ["--" | ";", ..]))
-                            .collect::<Vec<_>>();
+pub fn compile(source: &Source) -> Result<()> {
+    let program = parse(source)?;
+    let mut vm = Vm::new(&program);
 
-    let labels: Labels = line_splits.iter().enumerate().filter_map(|(i, s)| find_label(i, s.as_slice())).collect();
-    let procedures: Procedures = find_procedures(line_splits.as_slice());
-    let instructions: Vec<Instruction> = line_splits.iter().map(|s| parse_instruction(s.as_slice(), &labels, &procedures)).collect();
+    vm.run()
+}
+
+// What integer arithmetic does when the result doesn't fit in an `isize`. Division by zero
+// always fails, whatever the mode.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OverflowMode {
+    Trap, // fail with an overflow error, the default
+    Wrap,
+    Saturate, // clamp to `isize::MIN` / `isize::MAX`
+}
+
+impl OverflowMode {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "trap" => Some(OverflowMode::Trap),
+            "wrap" => Some(OverflowMode::Wrap),
+            "saturate" => Some(OverflowMode::Saturate),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OverflowMode::Trap => "trap",
+            OverflowMode::Wrap => "wrap",
+            OverflowMode::Saturate => "saturate",
+        }
+    }
+
+    // Picks the result for this mode, `checked` is `None` when the operation overflowed.
+    #[inline(always)]
+    fn apply(self, instruction: &Instruction, checked: Option<isize>, wrapping: isize, saturating: isize) -> Result<isize> {
+        match (checked, self) {
+            (Some(v), _) => Ok(v),
+            (None, OverflowMode::Trap) => Err(ErrorKind::Overflow(instruction.mnemonic()).into()),
+            (None, OverflowMode::Wrap) => Ok(wrapping),
+            (None, OverflowMode::Saturate) => Ok(saturating),
+        }
+    }
+}
+
+// The expected type of a map key, for type errors.
+const KEY: &str = "int or str";
+
+// A host function callable from scripts via `ncall`, it receives the stack to consume and push values.
+pub type Native<'a> = Box<dyn FnMut(&mut Stack) -> Result<()> + 'a>;
+
+// The settings directives can change, as the host set them up.
+#[derive(Debug, Copy, Clone)]
+struct Settings {
+    overflow: OverflowMode,
+    lenient: bool,
+    trace: bool,
+}
 
-    run(&instructions[..]);
+pub struct Vm<'a> {
+    program: &'a Program,
+    stack: Stack,
+    call_stack: CallStack,
+    pointer: Pointer,
+    natives: HashMap<String, Native<'a>>,
+    output: Box<dyn Write + 'a>,
+    input: Box<dyn BufRead + 'a>,
+    args: Vec<Rc<str>>,
+    exit_code: Option<u8>,
+    files: FilePolicy,
+    overflow: OverflowMode,
+    lenient: bool,
+    trace: bool,
+    trace_output: Box<dyn Write + 'a>,
+    configured: Settings, // what `reset` goes back to, directives only change the fields above
+    listing: Vec<String>, // instruction text for the trace, filled in the first time it's needed
 }
 
-fn run(program: Program<'_>) {
-    use Instruction::*;
+impl<'a> Vm<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        Vm::with_stack(program, Stack::default())
+    }
+
+    // Starts the program with a pre-populated stack.
+    pub fn with_stack(program: &'a Program, stack: Stack) -> Self {
+        Vm { program,
+             stack,
+             call_stack: CallStack::new(),
+             pointer: 0,
+             natives: HashMap::new(),
+             output: Box::new(io::stdout()),
+             input: Box::new(BufReader::new(io::stdin())),
+             args: Vec::new(),
+             exit_code: None,
+             files: FilePolicy::disabled(),
+             overflow: OverflowMode::Trap,
+             lenient: false,
+             trace: false,
+             trace_output: Box::new(io::stderr()),
+             configured: Settings { overflow: OverflowMode::Trap,
+                                    lenient: false,
+                                    trace: false },
+             listing: Vec::new() }
+    }
+
+    // Redirects everything the `prnt*` instructions write, stdout is used by default.
+    pub fn set_output<W>(&mut self, w: W)
+        where W: Write + 'a {
+        self.output = Box::new(w);
+    }
+
+    // Replaces what the `rd*` instructions read from, stdin is used by default.
+    pub fn set_input<R>(&mut self, r: R)
+        where R: BufRead + 'a {
+        self.input = Box::new(r);
+    }
+
+    // The arguments `argc` and `argv` see, there are none by default.
+    pub fn set_args<I, S>(&mut self, args: I)
+        where I: IntoIterator<Item = S>,
+              S: Into<Rc<str>> {
+        self.args = args.into_iter().map(Into::into).collect();
+    }
+
+    // The code given to `exit`, `None` if the program ran off its end or hasn't halted yet.
+    pub fn exit_code(&self) -> Option<u8> {
+        self.exit_code
+    }
+
+    // Sets which files `fopen` can open, file access is disabled by default.
+    pub fn set_file_policy(&mut self, policy: FilePolicy) {
+        self.files = policy;
+    }
+
+    // Sets how integer overflow is handled, the same as an `#overflow` directive.
+    pub fn set_overflow(&mut self, overflow: OverflowMode) {
+        self.overflow = overflow;
+        self.configured.overflow = overflow;
+    }
+
+    // In lenient mode an instruction given operands of the wrong type drops them instead of
+    // failing, the same as a `#lenient on` directive.
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+        self.configured.lenient = lenient;
+    }
+
+    // Turns tracing on or off, the same as a `#trace on` / `#trace off` directive.
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+        self.configured.trace = trace;
+    }
+
+    // Redirects the trace, stderr is used by default.
+    pub fn set_trace_output<W>(&mut self, w: W)
+        where W: Write + 'a {
+        self.trace_output = Box::new(w);
+    }
+
+    // Registers a native function under `name`, replacing any previous one with the same name.
+    pub fn register<F>(&mut self, name: &str, f: F)
+        where F: FnMut(&mut Stack) -> Result<()> + 'a {
+        self.natives.insert(name.to_string(), Box::new(f));
+    }
+
+    pub fn program(&self) -> &'a Program {
+        self.program
+    }
+
+    pub fn stack(&self) -> &Stack {
+        &self.stack
+    }
+
+    pub fn stack_mut(&mut self) -> &mut Stack {
+        &mut self.stack
+    }
+
+    pub fn call_stack(&self) -> &CallStack {
+        &self.call_stack
+    }
+
+    pub fn pointer(&self) -> Pointer {
+        self.pointer
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.pointer >= self.program.instructions.len()
+    }
+
+    // Clears all state so the program can be run again from the start, settings changed by
+    // directives go back to what the host set.
+    pub fn reset(&mut self) {
+        self.stack = Stack::default();
+        self.call_stack.clear();
+        self.pointer = 0;
+        self.exit_code = None;
+        self.overflow = self.configured.overflow;
+        self.lenient = self.configured.lenient;
+        self.trace = self.configured.trace;
+    }
+
+    // Runs until the instruction pointer falls off the end of the program.
+    pub fn run(&mut self) -> Result<()> {
+        let result = self.run_to_end();
+        self.flush()?;
+
+        result
+    }
+
+    // Flushes the output and the trace, `run` does this once the program halts but `step` leaves it
+    // to the caller.
+    pub fn flush(&mut self) -> Result<()> {
+        self.output.flush()?;
+        self.trace_output.flush()?;
+
+        Ok(())
+    }
+
+    // Tracing is checked once per run of untraced instructions rather than on every step, so a
+    // program that doesn't trace pays nothing for it.
+    fn run_to_end(&mut self) -> Result<()> {
+        loop {
+            let running = if self.trace { self.step_traced()? } else { self.run_untraced()? };
+
+            if !running {
+                return Ok(());
+            }
+        }
+    }
+
+    // Runs until the program halts or an instruction turns tracing on, returns `false` once halted.
+    fn run_untraced(&mut self) -> Result<bool> {
+        while !self.trace {
+            if !self.execute_next()? {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    // Executes a single instruction, returns `false` once the program has halted.
+    pub fn step(&mut self) -> Result<bool> {
+        if self.trace {
+            self.step_traced()
+        } else {
+            self.execute_next_outlined()
+        }
+    }
+
+    #[inline(never)]
+    fn step_traced(&mut self) -> Result<bool> {
+        let pointer = self.pointer;
+        // Containers are shared and may change in place, so the stack is described up front rather
+        // than compared afterwards.
+        let before = self.stack.values.iter().map(describe).collect::<Vec<_>>();
+        self.stack.mark();
+
+        if !self.execute_next_outlined()? {
+            return Ok(false);
+        }
+
+        // The `#trace` directive that turned tracing on or off isn't traced itself.
+        if !matches!(self.program.instructions[pointer].node, Instruction::Trace(_)) {
+            self.trace_step(pointer, &before)?;
+        }
+
+        Ok(true)
+    }
+
+    // A copy of `execute_next` for single steps, keeping the inlined one in `run_untraced` alone.
+    #[inline(never)]
+    fn execute_next_outlined(&mut self) -> Result<bool> {
+        self.execute_next()
+    }
+
+    #[inline(always)]
+    fn execute_next(&mut self) -> Result<bool> {
+        let program = self.program;
+
+        let Some(Spanned { node: instruction, span }) = program.instructions.get(self.pointer) else {
+            return Ok(false);
+        };
+
+        self.pointer += 1;
+        self.execute(instruction, span).map_err(|e| e.at(*span))?;
+
+        Ok(true)
+    }
+
+    // Writes one trace line for the instruction at `pointer`, describing how it changed the stack
+    // from `before` and whether it jumped.
+    //
+    // 0004 add                             pop int 2, int 3; push int 5
+    // 0007 je done                         pop int 0; jump 0012
+    // 0010 setarg 0                        [1] int 4 => int 8
+    #[cold]
+    fn trace_step(&mut self, pointer: Pointer, before: &[String]) -> Result<()> {
+        if self.listing.is_empty() {
+            self.listing = listing(self.program);
+        }
+
+        let after = self.stack.values.iter().map(describe).collect::<Vec<_>>();
+        let mut low = self.stack.low().min(before.len());
+        let mut effects = Vec::new();
+
+        // The top changed in place, as `incr` does, reads better as popping and pushing it.
+        if low > 0 && low == before.len() && low == after.len() && before[low - 1] != after[low - 1] {
+            low -= 1;
+        }
+
+        // Slots below the top were overwritten, as `set` and `setarg` do, or hold a container that
+        // was changed through another handle to it.
+        for (i, (old, new)) in before[..low].iter().zip(&after).enumerate() {
+            if old != new {
+                effects.push(format!("[{}] {} => {}", i, old, new));
+            }
+        }
+
+        if low < before.len() {
+            effects.push(format!("pop {}", before[low..].join(", ")));
+        }
+        if low < after.len() {
+            effects.push(format!("push {}", after[low..].join(", ")));
+        }
 
-    let mut stack: Stack = Stack { values: Vec::new(),
-                                   hashmap: HashMap::new(),
-                                   registers: HashMap::new() };
-    let mut pointer: Pointer = 0;
-    let mut call_stack = CallStack::new();
+        if self.pointer != pointer + 1 {
+            effects.push(format!("jump {:04}", self.pointer));
+        }
+
+        let line = format!("{:04} {:<32}{}", pointer, self.listing[pointer], effects.join("; "));
+        writeln!(self.trace_output, "{}", line.trim_end())?;
 
-    while let Some(instruction) = program.get(pointer) {
-        pointer += 1;
+        Ok(())
+    }
+
+    // The printing instructions. Writing through `Box<dyn Write>` and formatting take a lot of code,
+    // kept out of line so it doesn't weigh on the dispatch in `execute`.
+    #[cold]
+    #[inline(never)]
+    fn print(&mut self, instruction: &Instruction, span: &Span) -> Result<()> {
+        use Instruction::*;
+        use StackValue::Int;
 
         match instruction {
-            Noop => {}
-            PushInt(d) => {
-                stack.push_as_value(*d);
+            Prnt => write!(self.output, "{}", self.stack.peek()?)?,
+            PrntStr(d) => writeln!(self.output, "{}", d)?,
+            Prntln => writeln!(self.output, "{}", self.stack.peek()?)?,
+            PrntC => match self.stack.peek()? {
+                Int(v) => write!(self.output, "{}", v as u8 as char)?,
+                v => write!(self.output, "{}", v)?,
+            },
+            PrntCln => match self.stack.peek()? {
+                Int(v) => writeln!(self.output, "{}", v as u8 as char)?,
+                v => writeln!(self.output, "{}", v)?,
+            },
+            PrntStk => {
+                writeln!(self.output, "--> {}:{}", self.program.name, span)?;
+                self.stack.print(&mut self.output)?;
             }
-            PushFlt(d) => {
-                stack.push_hashed_float(*d);
+            PrntReg => {
+                self.stack.print_registers(&mut self.output)?;
             }
-            PushStr(d) => stack.push_hashed_string(d),
+            _ => unreachable!(),
+        }
+
+        Ok(())
+    }
+
+    // The next line of input without its line ending, or `None` at the end of the input. Like `rdc`
+    // it flushes what the program printed first, so a prompt shows up before the read blocks.
+    fn read_line(&mut self) -> Result<Option<String>> {
+        self.output.flush()?;
+
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let len = line.trim_end_matches(['\n', '\r']).len();
+        line.truncate(len);
+
+        Ok(Some(line))
+    }
+
+    // Inlined into `run_untraced`, like the helpers it calls on the hot path, so a simple instruction
+    // costs little more than the work it does. Families of instructions that do more work are handled
+    // out of line, the code for them would otherwise crowd out the registers the rest need.
+    #[inline(always)]
+    fn execute(&mut self, instruction: &Instruction, span: &Span) -> Result<()> {
+        use Instruction::*;
+        use StackValue::{Double, Float, Int, Str};
+
+        match instruction {
+            Noop => {}
+            PushInt(d) => self.stack.push_int(*d),
+            PushFlt(d) => self.stack.push_float(*d),
+            PushDbl(d) => self.stack.push_double(*d),
+            PushStr(d) => self.stack.push_str(d),
             Pop => {
-                stack.pop();
+                self.stack.pop()?;
             }
             Dup => {
-                let a = stack.peek();
-
-                if a.hashed {
-                    stack.push_as_hashed(a.value);
-                } else {
-                    stack.push_as_value(a.value);
-                }
+                let a = self.stack.peek()?;
+                self.stack.push(a);
             }
             Swap => {
-                let (a, b) = (stack.pop(), stack.pop());
-
-                if a.hashed {
-                    stack.push_as_hashed(a.value);
-                } else {
-                    stack.push_as_value(a.value);
-                }
+                let (a, b) = (self.stack.pop()?, self.stack.pop()?);
 
-                if b.hashed {
-                    stack.push_as_hashed(b.value);
-                } else {
-                    stack.push_as_value(b.value);
-                }
+                self.stack.push(a);
+                self.stack.push(b);
             }
-            ClrStk => stack.clear_stack(),
+            ClrStk => self.stack.clear_stack(),
             DlcStk => {
-                stack.shrink_stack();
+                self.stack.shrink_stack();
             }
-            Add => {
-                let (a, b) = (stack.pop(), stack.pop());
-
-                if !a.hashed && !b.hashed {
-                    stack.push_as_value(a.value + b.value)
+            Add => match (self.stack.pop()?, self.stack.pop()?) {
+                (Int(a), Int(b)) => {
+                    let v = self.overflow.apply(instruction, b.checked_add(a), b.wrapping_add(a), b.saturating_add(a))?;
+                    self.stack.push_int(v);
                 }
-            }
-            AddF => {
-                let (a, b) = (stack.pop(), stack.pop());
-
-                if a.hashed && b.hashed {
-                    if let (Some(ValueType::Float(a)), Some(ValueType::Float(b))) = (stack.hashmap.get(&a.value), stack.hashmap.get(&b.value)) {
-                        stack.push_hashed_float(a + b);
-                    }
+                (a, b) => self.mismatch(instruction, &[Type::INT, Type::INT], &[b, a])?,
+            },
+            AddF => match (self.stack.pop()?, self.stack.pop()?) {
+                (Float(a), Float(b)) => self.stack.push_float(a + b),
+                (a, b) => self.mismatch(instruction, &[Type::FLOAT, Type::FLOAT], &[b, a])?,
+            },
+            Sub => match (self.stack.pop()?, self.stack.pop()?) {
+                (Int(a), Int(b)) => {
+                    let v = self.overflow.apply(instruction, b.checked_sub(a), b.wrapping_sub(a), b.saturating_sub(a))?;
+                    self.stack.push_int(v);
                 }
-            }
-            Sub => {
-                let (a, b) = (stack.pop(), stack.pop());
-
-                if !a.hashed && !b.hashed {
-                    stack.push_as_value(b.value - a.value)
+                (a, b) => self.mismatch(instruction, &[Type::INT, Type::INT], &[b, a])?,
+            },
+            SubF => match (self.stack.pop()?, self.stack.pop()?) {
+                (Float(a), Float(b)) => self.stack.push_float(b - a),
+                (a, b) => self.mismatch(instruction, &[Type::FLOAT, Type::FLOAT], &[b, a])?,
+            },
+            Mul => match (self.stack.pop()?, self.stack.pop()?) {
+                (Int(a), Int(b)) => {
+                    let v = self.overflow.apply(instruction, b.checked_mul(a), b.wrapping_mul(a), b.saturating_mul(a))?;
+                    self.stack.push_int(v);
                 }
-            }
-            SubF => {
-                let (a, b) = (stack.pop(), stack.pop());
-
-                if a.hashed && b.hashed {
-                    if let (Some(ValueType::Float(a)), Some(ValueType::Float(b))) = (stack.hashmap.get(&a.value), stack.hashmap.get(&b.value)) {
-                        stack.push_hashed_float(b - a);
+                (a, b) => self.mismatch(instruction, &[Type::INT, Type::INT], &[b, a])?,
+            },
+            MulF => match (self.stack.pop()?, self.stack.pop()?) {
+                (Float(a), Float(b)) => self.stack.push_float(a * b),
+                (a, b) => self.mismatch(instruction, &[Type::FLOAT, Type::FLOAT], &[b, a])?,
+            },
+            // `isize::MIN / -1` is the only division that overflows.
+            Div => match (self.stack.pop()?, self.stack.pop()?) {
+                (Int(a), Int(b)) => {
+                    if a == 0 {
+                        return Err(ErrorKind::DivisionByZero(instruction.mnemonic()).into());
                     }
-                }
-            }
-            Mul => {
-                let (a, b) = (stack.pop(), stack.pop());
 
-                if !a.hashed && !b.hashed {
-                    stack.push_as_value(a.value * b.value)
+                    let v = self.overflow.apply(instruction, b.checked_div(a), b.wrapping_div(a), b.saturating_div(a))?;
+                    self.stack.push_int(v);
                 }
-            }
-            MulF => {
-                let (a, b) = (stack.pop(), stack.pop());
-
-                if a.hashed && b.hashed {
-                    if let (Some(ValueType::Float(a)), Some(ValueType::Float(b))) = (stack.hashmap.get(&a.value), stack.hashmap.get(&b.value)) {
-                        stack.push_hashed_float(a * b);
+                (a, b) => self.mismatch(instruction, &[Type::INT, Type::INT], &[b, a])?,
+            },
+            DivF => match (self.stack.pop()?, self.stack.pop()?) {
+                (Float(a), Float(b)) => self.stack.push_float(b / a),
+                (a, b) => self.mismatch(instruction, &[Type::FLOAT, Type::FLOAT], &[b, a])?,
+            },
+            Mod => match (self.stack.pop()?, self.stack.pop()?) {
+                (Int(a), Int(b)) => {
+                    if a == 0 {
+                        return Err(ErrorKind::DivisionByZero(instruction.mnemonic()).into());
                     }
-                }
-            }
-            Div => {
-                let (a, b) = (stack.pop(), stack.pop());
 
-                if !a.hashed && !b.hashed {
-                    stack.push_as_value(b.value / a.value)
+                    let v = self.overflow.apply(instruction, b.checked_rem(a), b.wrapping_rem(a), 0)?;
+                    self.stack.push_int(v);
                 }
-            }
-            DivF => {
-                let (a, b) = (stack.pop(), stack.pop());
-
-                if a.hashed && b.hashed {
-                    if let (Some(ValueType::Float(a)), Some(ValueType::Float(b))) = (stack.hashmap.get(&a.value), stack.hashmap.get(&b.value)) {
-                        stack.push_hashed_float(b / a);
-                    }
-                }
-            }
-            Mod => {
-                let (a, b) = (stack.pop(), stack.pop());
+                (a, b) => self.mismatch(instruction, &[Type::INT, Type::INT], &[b, a])?,
+            },
+            ModF => match (self.stack.pop()?, self.stack.pop()?) {
+                (Float(a), Float(b)) => self.stack.push_float(b % a),
+                (a, b) => self.mismatch(instruction, &[Type::FLOAT, Type::FLOAT], &[b, a])?,
+            },
+            AddD => match (self.stack.pop()?, self.stack.pop()?) {
+                (Double(a), Double(b)) => self.stack.push_double(a + b),
+                (a, b) => self.mismatch(instruction, &[Type::DOUBLE, Type::DOUBLE], &[b, a])?,
+            },
+            SubD => match (self.stack.pop()?, self.stack.pop()?) {
+                (Double(a), Double(b)) => self.stack.push_double(b - a),
+                (a, b) => self.mismatch(instruction, &[Type::DOUBLE, Type::DOUBLE], &[b, a])?,
+            },
+            MulD => match (self.stack.pop()?, self.stack.pop()?) {
+                (Double(a), Double(b)) => self.stack.push_double(a * b),
+                (a, b) => self.mismatch(instruction, &[Type::DOUBLE, Type::DOUBLE], &[b, a])?,
+            },
+            DivD => match (self.stack.pop()?, self.stack.pop()?) {
+                (Double(a), Double(b)) => self.stack.push_double(b / a),
+                (a, b) => self.mismatch(instruction, &[Type::DOUBLE, Type::DOUBLE], &[b, a])?,
+            },
+            ModD => match (self.stack.pop()?, self.stack.pop()?) {
+                (Double(a), Double(b)) => self.stack.push_double(b % a),
+                (a, b) => self.mismatch(instruction, &[Type::DOUBLE, Type::DOUBLE], &[b, a])?,
+            },
+            ItoF => match self.stack.pop()? {
+                Int(v) => self.stack.push_float(v as f32),
+                v => self.mismatch(instruction, &[Type::INT], &[v])?,
+            },
+            ItoD => match self.stack.pop()? {
+                Int(v) => self.stack.push_double(v as f64),
+                v => self.mismatch(instruction, &[Type::INT], &[v])?,
+            },
+            FtoD => match self.stack.pop()? {
+                Float(v) => self.stack.push_double(v as f64),
+                v => self.mismatch(instruction, &[Type::FLOAT], &[v])?,
+            },
+            DtoF => match self.stack.pop()? {
+                Double(v) => self.stack.push_float(v as f32),
+                v => self.mismatch(instruction, &[Type::DOUBLE], &[v])?,
+            },
+            FtoI => match self.stack.pop()? {
+                Float(v) => self.stack.push_int(truncate(instruction, v as f64, Float(v))?),
+                v => self.mismatch(instruction, &[Type::FLOAT], &[v])?,
+            },
+            DtoI => match self.stack.pop()? {
+                Double(v) => self.stack.push_int(truncate(instruction, v, Double(v))?),
+                v => self.mismatch(instruction, &[Type::DOUBLE], &[v])?,
+            },
+            ItoS | FtoS | DtoS | StoI | StoF | StoD | StrCat | StrLen | SubStr | CharAt | StrFind | StrUp | StrLow | StrTrim | StrSplit | StrCmp | IntHas(_) | StrHas(_)
+            | FltHas(_) => self.strings(instruction)?,
+            ANew | ALoad | AStore | ALen | APush | APop | MNew | MSet | MGet(_) | MDel | MLen | MKeys => self.containers(instruction)?,
+            Cmp(p) => {
+                let (a, b) = (self.stack.pop()?, self.stack.pop()?);
 
-                if !a.hashed && !b.hashed {
-                    stack.push_as_value(b.value % a.value);
+                if a == b {
+                    self.stack.push(b);
+                    self.pointer = *p;
                 }
             }
-            ModF => {
-                let (a, b) = (stack.pop(), stack.pop());
+            Incr => {
+                let overflow = self.overflow;
 
-                if a.hashed && b.hashed {
-                    if let (Some(ValueType::Float(a)), Some(ValueType::Float(b))) = (stack.hashmap.get(&a.value), stack.hashmap.get(&b.value)) {
-                        stack.push_hashed_float(b % a);
+                match self.stack.peek_mut()? {
+                    Int(v) => *v = overflow.apply(instruction, v.checked_add(1), v.wrapping_add(1), v.saturating_add(1))?,
+                    v => {
+                        let v = v.clone();
+                        self.mismatch(instruction, &[Type::INT], &[v])?
                     }
                 }
             }
-            Cmp(p) => {
-                let (a, b) = (stack.pop(), stack.pop());
+            Decr => {
+                let overflow = self.overflow;
 
-                if a.hashed && b.hashed {
-                    if b.value == a.value {
-                        stack.push_as_hashed(b.value);
-                        pointer = *p;
+                match self.stack.peek_mut()? {
+                    Int(v) => *v = overflow.apply(instruction, v.checked_sub(1), v.wrapping_sub(1), v.saturating_sub(1))?,
+                    v => {
+                        let v = v.clone();
+                        self.mismatch(instruction, &[Type::INT], &[v])?
                     }
-                } else if !a.hashed && !b.hashed && b.value == a.value {
-                    stack.push_as_value(b.value);
-                    pointer = *p;
                 }
             }
-            Incr => stack.peek_mut().value += 1,
-            Decr => stack.peek_mut().value -= 1,
+            And => match (self.stack.pop()?, self.stack.pop()?) {
+                (Int(a), Int(b)) => self.stack.push_int(b & a),
+                (a, b) => self.mismatch(instruction, &[Type::INT, Type::INT], &[b, a])?,
+            },
+            Or => match (self.stack.pop()?, self.stack.pop()?) {
+                (Int(a), Int(b)) => self.stack.push_int(b | a),
+                (a, b) => self.mismatch(instruction, &[Type::INT, Type::INT], &[b, a])?,
+            },
+            Xor => match (self.stack.pop()?, self.stack.pop()?) {
+                (Int(a), Int(b)) => self.stack.push_int(b ^ a),
+                (a, b) => self.mismatch(instruction, &[Type::INT, Type::INT], &[b, a])?,
+            },
+            Not => match self.stack.pop()? {
+                Int(v) => self.stack.push_int(!v),
+                v => self.mismatch(instruction, &[Type::INT], &[v])?,
+            },
+            Shl => match (self.stack.pop()?, self.stack.pop()?) {
+                (Int(a), Int(b)) => self.stack.push_int(b << shift(instruction, a)?),
+                (a, b) => self.mismatch(instruction, &[Type::INT, Type::INT], &[b, a])?,
+            },
+            Shr => match (self.stack.pop()?, self.stack.pop()?) {
+                (Int(a), Int(b)) => self.stack.push_int(b >> shift(instruction, a)?),
+                (a, b) => self.mismatch(instruction, &[Type::INT, Type::INT], &[b, a])?,
+            },
+            LShr => match (self.stack.pop()?, self.stack.pop()?) {
+                (Int(a), Int(b)) => self.stack.push_int(((b as usize) >> shift(instruction, a)?) as isize),
+                (a, b) => self.mismatch(instruction, &[Type::INT, Type::INT], &[b, a])?,
+            },
+            PopCnt => match self.stack.pop()? {
+                Int(v) => self.stack.push_int(v.count_ones() as isize),
+                v => self.mismatch(instruction, &[Type::INT], &[v])?,
+            },
+            // Rotating by a negative amount rotates the other way.
+            RotL => match (self.stack.pop()?, self.stack.pop()?) {
+                (Int(a), Int(b)) => self.stack.push_int(b.rotate_left(a.rem_euclid(isize::BITS as isize) as u32)),
+                (a, b) => self.mismatch(instruction, &[Type::INT, Type::INT], &[b, a])?,
+            },
+            RotR => match (self.stack.pop()?, self.stack.pop()?) {
+                (Int(a), Int(b)) => self.stack.push_int(b.rotate_right(a.rem_euclid(isize::BITS as isize) as u32)),
+                (a, b) => self.mismatch(instruction, &[Type::INT, Type::INT], &[b, a])?,
+            },
             Mov(d, p) => {
-                if p.is_negative() {
-                    let ind = p.abs();
-                    let pos = stack.len() - ind as usize;
-                    let val = stack.get(pos);
-                    stack.push_register(*d, *val);
-                } else {
-                    let a = *stack.get(*p as usize + call_stack.last().map_or(0, |s| s.stack_offset));
-                    stack.push_register(*d, a);
-                }
+                let i = self.stack_index(*p)?;
+                let a = self.stack.get(i)?.clone();
+                self.stack.push_register(*d, a);
             }
             Ld(d) => {
-                if let Some(register) = stack.registers.get(d) {
-                    if register.hashed {
-                        stack.push_as_hashed(register.value);
-                    } else if !register.hashed {
-                        stack.push_as_value(register.value);
-                    }
+                if let Some(register) = self.stack.registers.get(d) {
+                    self.stack.push(register.clone());
                 }
             }
+            // Values free themselves once nothing references them, the hash table instructions only
+            // check their operand and are otherwise no-ops.
             DmpHash(p) => {
-                if p.is_negative() {
-                    let ind = p.abs();
-                    let pos = stack.len() - ind as usize;
-                    let val = stack.get(pos);
-
-                    if val.hashed {
-                        stack.delete_hash(val.value);
-                    }
-                } else {
-                    let a = *stack.get(*p as usize + call_stack.last().map_or(0, |s| s.stack_offset));
+                let i = self.stack_index(*p)?;
+                self.stack.get(i)?;
+            }
+            ClrHash | DlcHash => {}
+            DmpReg(p) => {
+                self.stack.delete_register(*p);
+            }
+            ClrReg => self.stack.clear_registers(),
+            DlcReg => self.stack.shrink_registers(),
+            Jmp(p) => self.pointer = *p,
+            JE(p) => self.jump_if(instruction, *p, Type::INT, |v| v.as_int().map(|v| v == 0))?,
+            JFE(p) => self.jump_if(instruction, *p, Type::FLOAT, |v| v.as_float().map(|v| v == 0.0_f32))?,
+            JNE(p) => self.jump_if(instruction, *p, Type::INT, |v| v.as_int().map(|v| v != 0))?,
+            JFNE(p) => self.jump_if(instruction, *p, Type::FLOAT, |v| v.as_float().map(|v| v != 0.0_f32))?,
+            JGT(p) => self.jump_if(instruction, *p, Type::INT, |v| v.as_int().map(|v| v > 0))?,
+            JFGT(p) => self.jump_if(instruction, *p, Type::FLOAT, |v| v.as_float().map(|v| v > 0.0_f32))?,
+            JLT(p) => self.jump_if(instruction, *p, Type::INT, |v| v.as_int().map(|v| v < 0))?,
+            JFLT(p) => self.jump_if(instruction, *p, Type::FLOAT, |v| v.as_float().map(|v| v < 0.0_f32))?,
+            JGE(p) => self.jump_if(instruction, *p, Type::INT, |v| v.as_int().map(|v| v >= 0))?,
+            JFGE(p) => self.jump_if(instruction, *p, Type::FLOAT, |v| v.as_float().map(|v| v >= 0.0_f32))?,
+            JLE(p) => self.jump_if(instruction, *p, Type::INT, |v| v.as_int().map(|v| v <= 0))?,
+            JFLE(p) => self.jump_if(instruction, *p, Type::FLOAT, |v| v.as_float().map(|v| v <= 0.0_f32))?,
+            JDE(p) => self.jump_if(instruction, *p, Type::DOUBLE, |v| v.as_double().map(|v| v == 0.0_f64))?,
+            JDNE(p) => self.jump_if(instruction, *p, Type::DOUBLE, |v| v.as_double().map(|v| v != 0.0_f64))?,
+            JDGT(p) => self.jump_if(instruction, *p, Type::DOUBLE, |v| v.as_double().map(|v| v > 0.0_f64))?,
+            JDLT(p) => self.jump_if(instruction, *p, Type::DOUBLE, |v| v.as_double().map(|v| v < 0.0_f64))?,
+            JDGE(p) => self.jump_if(instruction, *p, Type::DOUBLE, |v| v.as_double().map(|v| v >= 0.0_f64))?,
+            JDLE(p) => self.jump_if(instruction, *p, Type::DOUBLE, |v| v.as_double().map(|v| v <= 0.0_f64))?,
+            Get(i) => {
+                let a = self.stack.get(self.local_index(*i)?)?.clone();
+                self.stack.push(a);
+            }
+            Set(i) => {
+                let a = self.local_index(*i)?;
+                *self.stack.get_mut(a)? = self.stack.peek()?;
+            }
+            GetArg(i) => {
+                let a = self.stack.get(self.arg_index(*i)?)?.clone();
+                self.stack.push(a);
+            }
+            SetArg(i) => {
+                let offset_i = self.arg_index(*i)?;
+                let new_val = self.stack.peek()?;
 
-                    if a.hashed {
-                        stack.delete_hash(a.value);
-                    }
-                }
+                *self.stack.get_mut(offset_i)? = new_val;
             }
-            ClrHash => {
-                stack.clear_hashmap();
+            Prnt | PrntStr(_) | Prntln | PrntC | PrntCln | PrntStk | PrntReg => self.print(instruction, span)?,
+            RdLn(_) | RdInt(_) | RdFlt(_) | RdC => self.read(instruction)?,
+            Call(p) => {
+                self.call_stack.push(StackFrame { stack_offset: self.stack.len(),
+                                                  ip: self.pointer });
+                self.pointer = *p;
             }
-            DlcHash => stack.shrink_hashmap(),
-            DmpReg(p) => {
-                stack.delete_register(*p);
+            NCall(name) => {
+                let native = self.natives.get_mut(name).ok_or_else(|| ErrorKind::UnknownNative(name.to_string()))?;
+                native(&mut self.stack)?;
             }
-            ClrReg => stack.clear_registers(),
-            DlcReg => stack.shrink_registers(),
-            Jmp(p) => pointer = *p,
-            IntHas(p) => {
-                let (a, b) = (stack.pop(), stack.pop());
-
-                if !a.hashed && !b.hashed {
-                    let (str1, str2) = (&a.value.to_string(), &b.value.to_string());
-
-                    if str2.contains(str1) {
-                        stack.push_as_value(b.value);
-                        pointer = *p;
-                    }
+            Argc => self.stack.push_int(self.args.len() as isize),
+            Argv => match self.stack.pop()? {
+                Int(i) => {
+                    let arg = self.args[index(instruction, i, self.args.len())?].clone();
+                    self.stack.push(Str(arg));
                 }
+                v => self.mismatch(instruction, &[Type::INT], &[v])?,
+            },
+            Exit(code) => {
+                self.exit_code = Some(*code);
+                self.pointer = self.program.instructions.len();
             }
-            StrHas(p) => {
-                let (a, b) = (stack.pop(), stack.pop());
-
-                if a.hashed && b.hashed {
-                    if let (Some(ValueType::String(str1)), Some(ValueType::String(str2))) = (stack.hashmap.get(&a.value), stack.hashmap.get(&b.value)) {
-                        if str2.contains(str1) {
-                            stack.push_as_hashed(b.value);
-                            pointer = *p;
-                        }
+            FOpen | FReadLn(_) | FReadAll | FWrite | FClose => self.files(instruction)?,
+            Ret => self.pointer = self.call_stack.pop().ok_or_else(|| Error::from(ErrorKind::MissingCallFrame))?.ip,
+            Trace(on) => self.trace = *on,
+            Overflow(mode) => self.overflow = *mode,
+            Lenient(on) => self.lenient = *on,
+        }
+
+        Ok(())
+    }
+
+    // Conversions to and from strings, the string instructions and the `*has` jumps.
+    #[inline(never)]
+    fn strings(&mut self, instruction: &Instruction) -> Result<()> {
+        use Instruction::*;
+        use StackValue::{Double, Float, Int, Str};
+
+        match instruction {
+            ItoS => match self.stack.pop()? {
+                Int(v) => self.stack.push_str(&v.to_string()),
+                v => self.mismatch(instruction, &[Type::INT], &[v])?,
+            },
+            FtoS => match self.stack.pop()? {
+                Float(v) => self.stack.push_str(&v.to_string()),
+                v => self.mismatch(instruction, &[Type::FLOAT], &[v])?,
+            },
+            DtoS => match self.stack.pop()? {
+                Double(v) => self.stack.push_str(&v.to_string()),
+                v => self.mismatch(instruction, &[Type::DOUBLE], &[v])?,
+            },
+            StoI => match self.stack.pop()? {
+                Str(s) => self.stack.push_int(parse_str(instruction, &s, Type::INT)?),
+                v => self.mismatch(instruction, &[Type::STRING], &[v])?,
+            },
+            StoF => match self.stack.pop()? {
+                Str(s) => self.stack.push_float(parse_str(instruction, &s, Type::FLOAT)?),
+                v => self.mismatch(instruction, &[Type::STRING], &[v])?,
+            },
+            StoD => match self.stack.pop()? {
+                Str(s) => self.stack.push_double(parse_str(instruction, &s, Type::DOUBLE)?),
+                v => self.mismatch(instruction, &[Type::STRING], &[v])?,
+            },
+            StrCat => match (self.stack.pop()?, self.stack.pop()?) {
+                (Str(a), Str(b)) => self.stack.push_str(&format!("{}{}", b, a)),
+                (a, b) => self.mismatch(instruction, &[Type::STRING, Type::STRING], &[b, a])?,
+            },
+            StrLen => match self.stack.pop()? {
+                Str(s) => self.stack.push_int(s.chars().count() as isize),
+                v => self.mismatch(instruction, &[Type::STRING], &[v])?,
+            },
+            SubStr => match (self.stack.pop()?, self.stack.pop()?, self.stack.pop()?) {
+                (Int(n), Int(start), Str(s)) => {
+                    if n < 0 {
+                        return Err(ErrorKind::InvalidLength(instruction.mnemonic(), n).into());
                     }
+
+                    let chars = s.chars().collect::<Vec<_>>();
+                    let start = bound(instruction, start, chars.len())?;
+                    let end = bound(instruction, (start as isize).saturating_add(n), chars.len())?;
+
+                    self.stack.push_str(&chars[start..end].iter().collect::<String>());
                 }
-            }
-            FltHas(p) => {
-                let (a, b) = (stack.pop(), stack.pop());
-
-                if a.hashed && b.hashed {
-                    if let (Some(ValueType::Float(str1)), Some(ValueType::Float(str2))) = (stack.hashmap.get(&a.value), stack.hashmap.get(&b.value)) {
-                        if str2.to_string().contains(&str1.to_string()) {
-                            stack.push_as_hashed(b.value);
-                            pointer = *p;
-                        }
-                    }
+                (a, b, c) => self.mismatch(instruction, &[Type::STRING, Type::INT, Type::INT], &[c, b, a])?,
+            },
+            CharAt => match (self.stack.pop()?, self.stack.pop()?) {
+                (Int(i), Str(s)) => {
+                    let c = usize::try_from(i).ok().and_then(|i| s.chars().nth(i));
+                    let c = c.ok_or_else(|| ErrorKind::OutOfBounds(instruction.mnemonic(), i, s.chars().count()))?;
+
+                    self.stack.push_str(c.encode_utf8(&mut [0; 4]));
                 }
-            }
-            JE(p) => {
-                if stack.peek().value == 0 {
-                    stack.pop();
-                    pointer = *p;
+                (a, b) => self.mismatch(instruction, &[Type::STRING, Type::INT], &[b, a])?,
+            },
+            StrFind => match (self.stack.pop()?, self.stack.pop()?) {
+                (Str(a), Str(b)) => {
+                    let i = b.find(&*a).map_or(-1, |i| b[..i].chars().count() as isize);
+                    self.stack.push_int(i);
                 }
-            }
-            JFE(p) => {
-                let a = stack.peek();
-
-                if a.hashed {
-                    if let Some(ValueType::Float(v)) = stack.hashmap.get(&a.value) {
-                        if *v == 0.0_f32 {
-                            stack.pop();
-                            pointer = *p;
-                        }
+                (a, b) => self.mismatch(instruction, &[Type::STRING, Type::STRING], &[b, a])?,
+            },
+            StrUp => match self.stack.pop()? {
+                Str(s) => self.stack.push_str(&s.to_uppercase()),
+                v => self.mismatch(instruction, &[Type::STRING], &[v])?,
+            },
+            StrLow => match self.stack.pop()? {
+                Str(s) => self.stack.push_str(&s.to_lowercase()),
+                v => self.mismatch(instruction, &[Type::STRING], &[v])?,
+            },
+            StrTrim => match self.stack.pop()? {
+                Str(s) => self.stack.push_str(s.trim()),
+                v => self.mismatch(instruction, &[Type::STRING], &[v])?,
+            },
+            // Pushes every part, then how many there were.
+            StrSplit => match (self.stack.pop()?, self.stack.pop()?) {
+                (Str(a), Str(b)) => {
+                    // Splitting on "" would give an empty part at each end as well as every char.
+                    if a.is_empty() {
+                        return Err(ErrorKind::EmptySeparator(instruction.mnemonic()).into());
                     }
+
+                    let mut n = 0;
+
+                    for part in b.split(&*a) {
+                        self.stack.push_str(part);
+                        n += 1;
+                    }
+
+                    self.stack.push_int(n);
                 }
-            }
-            JNE(p) => {
-                if stack.peek().value != 0 {
-                    stack.pop();
-                    pointer = *p;
-                }
-            }
-            JFNE(p) => {
-                let a = stack.peek();
-
-                if a.hashed {
-                    if let Some(ValueType::Float(v)) = stack.hashmap.get(&a.value) {
-                        if *v != 0.0_f32 {
-                            stack.pop();
-                            pointer = *p;
-                        }
+                (a, b) => self.mismatch(instruction, &[Type::STRING, Type::STRING], &[b, a])?,
+            },
+            // -1, 0 or 1 as the second item sorts before, the same as or after the top item.
+            StrCmp => match (self.stack.pop()?, self.stack.pop()?) {
+                (Str(a), Str(b)) => self.stack.push_int(b.cmp(&a) as isize),
+                (a, b) => self.mismatch(instruction, &[Type::STRING, Type::STRING], &[b, a])?,
+            },
+            IntHas(p) => match (self.stack.pop()?, self.stack.pop()?) {
+                (Int(a), Int(b)) => {
+                    if b.to_string().contains(&a.to_string()) {
+                        self.stack.push_int(b);
+                        self.pointer = *p;
                     }
                 }
-            }
-            JGT(p) => {
-                if stack.peek().value > 0 {
-                    stack.pop();
-                    pointer = *p;
+                (a, b) => self.mismatch(instruction, &[Type::INT, Type::INT], &[b, a])?,
+            },
+            StrHas(p) => match (self.stack.pop()?, self.stack.pop()?) {
+                (Str(a), Str(b)) => {
+                    if b.contains(&*a) {
+                        self.stack.push(Str(b));
+                        self.pointer = *p;
+                    }
                 }
-            }
-            JFGT(p) => {
-                let a = stack.peek();
-
-                if a.hashed {
-                    if let Some(ValueType::Float(v)) = stack.hashmap.get(&a.value) {
-                        if *v > 0.0_f32 {
-                            stack.pop();
-                            pointer = *p;
-                        }
+                (a, b) => self.mismatch(instruction, &[Type::STRING, Type::STRING], &[b, a])?,
+            },
+            FltHas(p) => match (self.stack.pop()?, self.stack.pop()?) {
+                (Float(a), Float(b)) => {
+                    if b.to_string().contains(&a.to_string()) {
+                        self.stack.push_float(b);
+                        self.pointer = *p;
                     }
                 }
-            }
-            JLT(p) => {
-                if stack.peek().value < 0 {
-                    stack.pop();
-                    pointer = *p;
+                (a, b) => self.mismatch(instruction, &[Type::FLOAT, Type::FLOAT], &[b, a])?,
+            },
+            _ => unreachable!(),
+        }
+
+        Ok(())
+    }
+
+    // The array and map instructions.
+    #[inline(never)]
+    fn containers(&mut self, instruction: &Instruction) -> Result<()> {
+        use Instruction::*;
+        use StackValue::{Array, Int, Map};
+
+        match instruction {
+            ANew => match self.stack.pop()? {
+                Int(n) => {
+                    let size = usize::try_from(n).map_err(|_| ErrorKind::InvalidSize(instruction.mnemonic(), n))?;
+                    let mut items = Vec::new();
+                    items.try_reserve_exact(size).map_err(|_| ErrorKind::AllocationFailed(instruction.mnemonic(), size))?;
+                    items.resize(size, Int(0));
+
+                    self.stack.push(Array(Rc::new(RefCell::new(items))));
                 }
-            }
-            JFLT(p) => {
-                let a = stack.peek();
-
-                if a.hashed {
-                    if let Some(ValueType::Float(v)) = stack.hashmap.get(&a.value) {
-                        if *v < 0.0_f32 {
-                            stack.pop();
-                            pointer = *p;
-                        }
-                    }
+                v => self.mismatch(instruction, &[Type::INT], &[v])?,
+            },
+            ALoad => match (self.stack.pop()?, self.stack.pop()?) {
+                (Int(i), Array(a)) => {
+                    let v = a.borrow()[index(instruction, i, a.borrow().len())?].clone();
+                    self.stack.push(v);
                 }
-            }
-            JGE(p) => {
-                if stack.peek().value >= 0 {
-                    stack.pop();
-                    pointer = *p;
+                (a, b) => self.mismatch(instruction, &[Type::ARRAY, Type::INT], &[b, a])?,
+            },
+            AStore => match (self.stack.pop()?, self.stack.pop()?, self.stack.pop()?) {
+                (v, Int(i), Array(a)) => {
+                    let i = index(instruction, i, a.borrow().len())?;
+                    a.borrow_mut()[i] = v;
                 }
-            }
-            JFGE(p) => {
-                let a = stack.peek();
-
-                if a.hashed {
-                    if let Some(ValueType::Float(v)) = stack.hashmap.get(&a.value) {
-                        if *v >= 0.0_f32 {
-                            stack.pop();
-                            pointer = *p;
-                        }
-                    }
+                (a, b, c) => self.mismatch(instruction, &[Type::ARRAY, Type::INT, "any"], &[c, b, a])?,
+            },
+            ALen => match self.stack.pop()? {
+                Array(a) => self.stack.push_int(a.borrow().len() as isize),
+                v => self.mismatch(instruction, &[Type::ARRAY], &[v])?,
+            },
+            APush => match (self.stack.pop()?, self.stack.pop()?) {
+                (v, Array(a)) => a.borrow_mut().push(v),
+                (a, b) => self.mismatch(instruction, &[Type::ARRAY, "any"], &[b, a])?,
+            },
+            APop => match self.stack.pop()? {
+                Array(a) => {
+                    let v = a.borrow_mut().pop().ok_or_else(|| ErrorKind::Empty(instruction.mnemonic()))?;
+                    self.stack.push(v);
                 }
-            }
-            JLE(p) => {
-                if stack.peek().value <= 0 {
-                    stack.pop();
-                    pointer = *p;
+                v => self.mismatch(instruction, &[Type::ARRAY], &[v])?,
+            },
+            MNew => self.stack.push(Map(Rc::new(RefCell::new(BTreeMap::new())))),
+            MSet => {
+                let (v, k, map) = (self.stack.pop()?, self.stack.pop()?, self.stack.pop()?);
+
+                match (MapKey::from_value(&k), map) {
+                    (Some(key), Map(m)) => {
+                        m.borrow_mut().insert(key, v);
+                    }
+                    (_, map) => self.mismatch(instruction, &[Type::MAP, KEY, "any"], &[map, k, v])?,
                 }
             }
-            JFLE(p) => {
-                let a = stack.peek();
-
-                if a.hashed {
-                    if let Some(ValueType::Float(v)) = stack.hashmap.get(&a.value) {
-                        if *v <= 0.0_f32 {
-                            stack.pop();
-                            pointer = *p;
+            MGet(p) => {
+                let (k, map) = (self.stack.pop()?, self.stack.pop()?);
+
+                match (MapKey::from_value(&k), map) {
+                    (Some(key), Map(m)) => {
+                        let v = m.borrow().get(&key).cloned();
+
+                        match v {
+                            Some(v) => self.stack.push(v),
+                            None => self.pointer = *p,
                         }
                     }
+                    (_, map) => self.mismatch(instruction, &[Type::MAP, KEY], &[map, k])?,
                 }
             }
-            Get(i) => {
-                let a = *stack.get(*i + call_stack.last().map_or(0, |s| s.stack_offset));
-                if a.hashed {
-                    stack.push_as_hashed(a.value);
-                } else if !a.hashed {
-                    stack.push_as_value(a.value);
+            MDel => {
+                let (k, map) = (self.stack.pop()?, self.stack.pop()?);
+
+                match (MapKey::from_value(&k), map) {
+                    (Some(key), Map(m)) => {
+                        m.borrow_mut().remove(&key);
+                    }
+                    (_, map) => self.mismatch(instruction, &[Type::MAP, KEY], &[map, k])?,
                 }
             }
-            Set(i) => {
-                let a = *i + call_stack.last().map_or(0, |s| s.stack_offset);
-                *stack.get_mut(a) = stack.peek();
-            }
-            GetArg(i) => {
-                let a = *stack.get(call_stack.last().unwrap().stack_offset - 1 - *i);
+            MLen => match self.stack.pop()? {
+                Map(m) => self.stack.push_int(m.borrow().len() as isize),
+                v => self.mismatch(instruction, &[Type::MAP], &[v])?,
+            },
+            MKeys => match self.stack.pop()? {
+                Map(m) => {
+                    let keys = m.borrow().keys().cloned().map(StackValue::from).collect();
+                    self.stack.push(Array(Rc::new(RefCell::new(keys))));
+                }
+                v => self.mismatch(instruction, &[Type::MAP], &[v])?,
+            },
+            _ => unreachable!(),
+        }
+
+        Ok(())
+    }
+
+    // The file instructions, `fopen` checks the path against the file access policy.
+    #[inline(never)]
+    fn files(&mut self, instruction: &Instruction) -> Result<()> {
+        use Instruction::*;
+        use StackValue::{File, Str};
 
-                if a.hashed {
-                    stack.push_as_hashed(a.value);
-                } else if !a.hashed {
-                    stack.push_as_value(a.value);
+        match instruction {
+            FOpen => match (self.stack.pop()?, self.stack.pop()?) {
+                (Str(mode), Str(path)) => {
+                    let file = self.files.open(&path, &mode)?;
+                    self.stack.push(File(file));
                 }
-            }
-            SetArg(i) => {
-                let offset_i = call_stack.last().unwrap().stack_offset - 1 - *i;
-                let new_val = stack.peek();
+                (a, b) => self.mismatch(instruction, &[Type::STRING, Type::STRING], &[b, a])?,
+            },
+            FReadLn(p) => match self.stack.pop()? {
+                File(f) => match f.read_line(instruction.mnemonic())? {
+                    Some(line) => self.stack.push(Str(line.into())),
+                    None => self.pointer = *p,
+                },
+                v => self.mismatch(instruction, &[Type::FILE], &[v])?,
+            },
+            FReadAll => match self.stack.pop()? {
+                File(f) => {
+                    let text = f.read_all(instruction.mnemonic())?;
+                    self.stack.push(Str(text.into()));
+                }
+                v => self.mismatch(instruction, &[Type::FILE], &[v])?,
+            },
+            FWrite => match (self.stack.pop()?, self.stack.pop()?) {
+                (v, File(f)) => f.write(instruction.mnemonic(), &v.to_string())?,
+                (a, b) => self.mismatch(instruction, &[Type::FILE, "any"], &[b, a])?,
+            },
+            FClose => match self.stack.pop()? {
+                File(f) => f.close()?,
+                v => self.mismatch(instruction, &[Type::FILE], &[v])?,
+            },
+            _ => unreachable!(),
+        }
 
-                *stack.get_mut(offset_i) = new_val;
-            }
-            Prnt => print!("{}", stack.peek().value),
-            PrntStr(d) => println!("{}", d),
-            Prntln => println!("{}", stack.peek().value),
-            PrntC => print!("{}", stack.peek().value as u8 as char),
-            PrntCln => println!("{}", stack.peek().value as u8 as char),
-            PrntStk => {
-                stack.print();
+        Ok(())
+    }
+
+    // The instructions reading from the input.
+    #[inline(never)]
+    fn read(&mut self, instruction: &Instruction) -> Result<()> {
+        use Instruction::*;
+        use StackValue::{Float, Str};
+
+        match instruction {
+            RdLn(p) => match self.read_line()? {
+                Some(line) => self.stack.push(Str(line.into())),
+                None => self.pointer = *p,
+            },
+            RdInt(p) => match self.read_line()?.and_then(|l| l.trim().parse().ok()) {
+                Some(v) => self.stack.push_int(v),
+                None => self.pointer = *p,
+            },
+            RdFlt(p) => match self.read_line()?.and_then(|l| l.trim().parse().ok()) {
+                Some(v) => self.stack.push(Float(v)),
+                None => self.pointer = *p,
+            },
+            RdC => {
+                self.output.flush()?;
+                let byte = self.input.fill_buf()?.first().copied();
+
+                match byte {
+                    Some(b) => {
+                        self.input.consume(1);
+                        self.stack.push_int(b as isize);
+                    }
+                    None => self.stack.push_int(-1),
+                }
             }
-            PrntReg => {
-                stack.print_registers();
+            _ => unreachable!(),
+        }
+
+        Ok(())
+    }
+
+    // Pops the top of the stack and jumps to `p` if `f` holds for it, otherwise leaves it in place.
+    // `f` gives `None` when the top isn't of type `t`.
+    #[inline(always)]
+    fn jump_if(&mut self, instruction: &Instruction, p: Pointer, t: &'static str, f: impl Fn(&StackValue) -> Option<bool>) -> Result<()> {
+        match self.stack.values.last().map(f) {
+            Some(Some(true)) => {
+                self.stack.pop()?;
+                self.pointer = p;
             }
-            Call(p) => {
-                call_stack.push(StackFrame { stack_offset: stack.len(),
-                                             ip: pointer });
-                pointer = *p;
+            Some(Some(false)) => {}
+            Some(None) => {
+                let v = self.stack.peek()?;
+                self.mismatch(instruction, &[t], &[v])?
             }
-            Ret => pointer = call_stack.pop().unwrap().ip,
+            None => return Err(ErrorKind::StackUnderflow.into()),
         }
+
+        Ok(())
     }
-}
 
-fn parse_instruction(s: &[&str], labels: &Labels, procedures: &Procedures) -> Instruction {
-    use Instruction::*;
-
-    match s {
-        ["pushint" | "pint", x] => PushInt(x.parse::<isize>().unwrap()),
-        ["pushfloat" | "pflt", x] => PushFlt(x.parse::<f32>().unwrap()),
-        ["pushstr" | "pstr", x] => PushStr(x.parse::<String>().unwrap()),
-        ["pop"] => Pop,
-        ["dup"] => Dup,
-        ["swap"] => Swap,
-        ["clrstk"] => ClrStk, // clear table
-        ["dlcstk"] => DlcStk, // shrink_to_fit / dealloc table
-        ["add"] => Add,       // int
-        ["addf"] => AddF,     // float
-        ["sub"] => Sub,       // int
-        ["subf"] => SubF,     // float
-        ["mul"] => Mul,       // int
-        ["mulf"] => MulF,     // float
-        ["div"] => Div,       // int
-        ["divf"] => DivF,     // float
-        ["mod"] => Mod,       // int
-        ["modf"] => ModF,     // float
-        ["incr"] => Incr,
-        ["decr"] => Decr,
-        ["mov", d, p] => Mov(d.parse::<isize>().unwrap(), p.parse::<isize>().unwrap()),
-        ["ld", d] => Ld(d.parse::<isize>().unwrap()),
-        ["dmphash", p] => DmpHash(p.parse::<isize>().unwrap()), // remove key
-        ["clrhash"] => ClrHash,                                 // clear table
-        ["dlchash"] => DlcHash,                                 // shrink_to_fit / dealloc table
-        ["dmpreg", p] => DmpReg(p.parse::<isize>().unwrap()),   // remove key
-        ["clrreg"] => ClrReg,                                   // clear table
-        ["dlcreg"] => DlcReg,                                   // shrink_to_fit / dealloc table
-        ["jmp", l] => Jmp(*labels.get(l).unwrap()),
-        ["cmp", l] => Cmp(*labels.get(l).unwrap()),
-        ["inthas", l] => IntHas(*labels.get(l).unwrap()),
-        ["strhas", l] => StrHas(*labels.get(l).unwrap()),
-        ["flthas", l] => FltHas(*labels.get(l).unwrap()),
-        ["je", l] => JE(*labels.get(l).unwrap()),     // int
-        ["jfe", l] => JFE(*labels.get(l).unwrap()),   // float
-        ["jne", l] => JNE(*labels.get(l).unwrap()),   // int
-        ["jfne", l] => JFNE(*labels.get(l).unwrap()), // float
-        ["jge", l] => JGE(*labels.get(l).unwrap()),   // int
-        ["jfge", l] => JFGE(*labels.get(l).unwrap()), // float
-        ["jle", l] => JLE(*labels.get(l).unwrap()),   // int
-        ["jfle", l] => JFLE(*labels.get(l).unwrap()), // float
-        ["jgt", l] => JGT(*labels.get(l).unwrap()),   // int
-        ["jfgt", l] => JFGT(*labels.get(l).unwrap()), // float
-        ["jlt", l] => JLT(*labels.get(l).unwrap()),   // int
-        ["jflt", l] => JFLT(*labels.get(l).unwrap()), // float
-        ["get", p] => Get(p.parse::<Pointer>().unwrap()),
-        ["set", p] => Set(p.parse::<Pointer>().unwrap()),
-        ["getarg", p] => GetArg(p.parse::<Pointer>().unwrap()),
-        ["setarg", p] => SetArg(p.parse::<Pointer>().unwrap()),
-        ["prnt"] => Prnt,
-        ["prntstr", d] => PrntStr(d.parse::<String>().unwrap()),
-        ["prntln"] => Prntln,
-        ["prntc"] => PrntC,
-        ["prntcln"] => PrntCln,
-        ["prntstk"] => PrntStk,
-        ["prntreg"] => PrntReg,
-        ["proc", proc] => Jmp(procedures.get(proc).unwrap().1),
-        ["call", proc] => Call(procedures.get(proc).unwrap().0 + 1),
-        ["ret"] => Ret,
-        ["label", ..] | ["end"] => Noop,
-        l => panic!("Invalid instruction: {:?}", l),
+    // Fails with a type error naming the instruction, unless lenient mode is on, in which case the
+    // instruction does nothing beyond popping its operands.
+    #[cold]
+    fn mismatch(&self, instruction: &Instruction, expected: &[&'static str], found: &[StackValue]) -> Result<()> {
+        if self.lenient {
+            return Ok(());
+        }
+
+        Err(ErrorKind::OperandMismatch(instruction.mnemonic(),
+                                       expected.join(", "),
+                                       found.iter().map(StackValue::type_name).collect::<Vec<_>>().join(", ")).into())
+    }
+
+    // Resolves a `mov` / `dmphash` style index, negative indices are relative to the top of the stack.
+    fn stack_index(&mut self, p: isize) -> Result<usize> {
+        if p.is_negative() {
+            self.stack.len().checked_sub(p.unsigned_abs()).ok_or_else(|| ErrorKind::InvalidIndex(p as i128).into())
+        } else {
+            self.local_index(p as usize)
+        }
+    }
+
+    // Resolves a `getarg` / `setarg` index, counting down from the top of the caller's stack. Like
+    // `local_index` it checks the slot exists, so the error names the index as written.
+    #[inline(always)]
+    fn arg_index(&self, i: Pointer) -> Result<usize> {
+        let frame = self.call_stack.last().ok_or_else(|| Error::from(ErrorKind::MissingCallFrame))?;
+
+        i.checked_add(1)
+         .and_then(|n| frame.stack_offset.checked_sub(n))
+         .filter(|&a| a < self.stack.values.len())
+         .ok_or_else(|| ErrorKind::InvalidIndex(i as i128).into())
+    }
+
+    // A `get` / `set` index, relative to the locals of the current procedure. Fails with the index
+    // as written rather than the absolute one, unless the slot exists.
+    #[inline(always)]
+    fn local_index(&self, i: Pointer) -> Result<usize> {
+        i.checked_add(self.call_stack.last().map_or(0, |s| s.stack_offset))
+         .filter(|&a| a < self.stack.values.len())
+         .ok_or_else(|| ErrorKind::InvalidIndex(i as i128).into())
     }
 }
 
-fn find_label<'a>(i: Pointer, s: &'a [&'a str]) -> Option<Label> {
-    if let ["label", l] = s {
-        Some((l, i))
+// Converts a float or double to an int by truncating towards zero, failing for NaN, infinities and
+// values outside the `isize` range. `value` is the original operand, for the error.
+fn truncate(instruction: &Instruction, v: f64, value: StackValue) -> Result<isize> {
+    // `isize::MAX as f64` rounds up to 2^63, which is just out of range.
+    if v.is_finite() && v >= isize::MIN as f64 && v < isize::MAX as f64 {
+        Ok(v as isize)
     } else {
-        None
+        Err(ErrorKind::InvalidConversion(instruction.mnemonic(), value.to_string(), Type::INT).into())
     }
 }
 
-fn find_procedures<'a>(lines: &'a [Vec<&str>]) -> Procedures<'a> {
-    let mut ip = 0;
-    let mut res = Procedures::new();
+// Parses the string operand of `stoi`, `stof` or `stod`, surrounding whitespace is ignored.
+fn parse_str<T: FromStr>(instruction: &Instruction, s: &str, t: &'static str) -> Result<T> {
+    s.trim().parse().map_err(|_| ErrorKind::InvalidConversion(instruction.mnemonic(), s.to_string(), t).into())
+}
 
-    while ip < lines.len() {
-        if let ["proc", proc_name] = lines[ip].as_slice() {
-            let start_ip = ip;
-            while lines[ip] != ["end"] {
-                ip += 1;
-            }
-            res.insert(proc_name, (start_ip, ip + 1));
-        } else {
-            ip += 1;
-        }
-    }
+// Checks an index into a sequence of `len` items, `len` itself is allowed as the end of a range.
+fn bound(instruction: &Instruction, i: isize, len: usize) -> Result<usize> {
+    usize::try_from(i).ok()
+                      .filter(|i| *i <= len)
+                      .ok_or_else(|| ErrorKind::OutOfBounds(instruction.mnemonic(), i, len).into())
+}
+
+// Checks a shift amount, shifting by the width of an `isize` or more is an error rather than zero.
+fn shift(instruction: &Instruction, n: isize) -> Result<u32> {
+    u32::try_from(n).ok()
+                    .filter(|n| *n < isize::BITS)
+                    .ok_or_else(|| ErrorKind::InvalidShift(instruction.mnemonic(), n).into())
+}
 
-    res
+// Checks an index into a sequence of `len` items.
+fn index(instruction: &Instruction, i: isize, len: usize) -> Result<usize> {
+    usize::try_from(i).ok()
+                      .filter(|i| *i < len)
+                      .ok_or_else(|| ErrorKind::OutOfBounds(instruction.mnemonic(), i, len).into())
 }
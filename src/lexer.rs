@@ -0,0 +1,109 @@
+use crate::error::{Error, ErrorKind, Result};
+use crate::span::{Span, Spanned};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Word(String), // mnemonics, numbers and names
+    Str(String),  // a quoted literal with its escapes resolved
+}
+
+impl Token {
+    pub fn text(&self) -> &str {
+        match self {
+            Token::Word(s) | Token::Str(s) => s,
+        }
+    }
+}
+
+// Splits one source line into tokens, `line` is 1-based.
+//
+// Tokens are separated by whitespace, `"..."` literals may contain whitespace and the escapes
+// `\n`, `\t`, `\r`, `\0`, `\"`, `\\` and `\u{...}`. A `;` outside of a literal, or a token starting
+// with `--`, comments out the rest of the line.
+pub fn lex_line(line: usize, s: &str) -> Result<Vec<Spanned<Token>>> {
+    let chars = s.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == ';' || (c == '-' && chars.get(i + 1) == Some(&'-')) {
+            break;
+        } else if c == '"' {
+            let start = i;
+            let (literal, end) = lex_string(&chars, i).map_err(|e| e.at(Span { line, col: start + 1, len: 1 }))?;
+            i = end;
+            tokens.push(Spanned { node: Token::Str(literal),
+                                  span: Span { line,
+                                               col: start + 1,
+                                               len: i - start } });
+        } else {
+            let start = i;
+            while i < chars.len() && !chars[i].is_whitespace() && chars[i] != ';' && chars[i] != '"' {
+                i += 1;
+            }
+            tokens.push(Spanned { node: Token::Word(chars[start..i].iter().collect()),
+                                  span: Span { line,
+                                               col: start + 1,
+                                               len: i - start } });
+        }
+    }
+
+    Ok(tokens)
+}
+
+// Reads a literal starting at the opening quote, returns it and the index just past the closing quote.
+fn lex_string(chars: &[char], start: usize) -> Result<(String, usize)> {
+    let mut literal = String::new();
+    let mut i = start + 1;
+
+    while let Some(&c) = chars.get(i) {
+        i += 1;
+
+        match c {
+            '"' => return Ok((literal, i)),
+            '\\' => {
+                let escape = chars.get(i).copied().ok_or(ErrorKind::UnterminatedString)?;
+                i += 1;
+
+                literal.push(match escape {
+                                 'n' => '\n',
+                                 't' => '\t',
+                                 'r' => '\r',
+                                 '0' => '\0',
+                                 '"' => '"',
+                                 '\\' => '\\',
+                                 'u' => {
+                                     let (c, end) = lex_unicode(chars, i)?;
+                                     i = end;
+                                     c
+                                 }
+                                 e => return Err(ErrorKind::InvalidEscape(format!("\\{}", e)).into()),
+                             });
+            }
+            c => literal.push(c),
+        }
+    }
+
+    Err(ErrorKind::UnterminatedString.into())
+}
+
+// Reads the `{...}` part of a `\u{...}` escape.
+fn lex_unicode(chars: &[char], start: usize) -> Result<(char, usize)> {
+    let invalid = |end: usize| -> Error { ErrorKind::InvalidEscape(format!("\\u{}", chars[start..end.min(chars.len())].iter().collect::<String>())).into() };
+
+    if chars.get(start) != Some(&'{') {
+        return Err(invalid(start));
+    }
+
+    let close = chars[start..].iter().position(|c| *c == '}').map(|p| start + p).ok_or_else(|| invalid(chars.len()))?;
+    let hex = chars[start + 1..close].iter().collect::<String>();
+
+    u32::from_str_radix(&hex, 16).ok()
+                                 .and_then(char::from_u32)
+                                 .map(|c| (c, close + 1))
+                                 .ok_or_else(|| invalid(close + 1))
+}
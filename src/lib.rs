@@ -1,3 +1,12 @@
+pub mod bytecode;
+pub mod debugger;
+pub mod disasm;
+pub mod error;
+pub mod files;
 pub mod instructions;
 pub mod interpreter;
+pub mod lexer;
+pub mod parser;
+pub mod program;
+pub mod span;
 pub mod stack;
@@ -1,14 +1,135 @@
-use asm::interpreter::compile;
-use std::io::Read;
+use asm::bytecode::{deserialize, is_bytecode, serialize};
+use asm::debugger::Debugger;
+use asm::disasm::disassemble;
+use asm::files::FilePolicy;
+use asm::interpreter::{OverflowMode, Vm};
+use asm::parser::parse;
+use asm::program::Program;
+use asm::span::Source;
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::process::ExitCode;
 
-fn main() -> std::io::Result<()> {
-    let args: Vec<String> = std::env::args().collect();
-    let mut f = std::fs::File::open(&args[1])?;
+const USAGE: &str = "usage: asm [run] [options] <file.asm | file.asmb> [-- <args>...]
+       asm assemble <file.asm> [-o <file.asmb>]
+       asm disasm <file.asm | file.asmb>
+       asm debug [options] <file.asm | file.asmb> [-- <args>...]
 
-    let mut buffer = String::new();
-    f.read_to_string(&mut buffer)?;
+arguments after `--` are passed to the script, where `argc` and `argv` read them. `run` exits with
+the code the script gives to `exit`, or 0 if it runs off its end.
 
-    compile(buffer);
+options:
+       --trace                              print every instruction as it runs to stderr, like `#trace on`
+       --overflow=<trap | wrap | saturate>  what integer overflow does, like `#overflow`, `trap` by default
+       --lenient                            drop operands of the wrong type instead of failing, like `#lenient on`
+       --allow-dir=<dir>                    let `fopen` open files under the directory, may be repeated
+       --read-only                          only let `fopen` open files for reading
+
+file access is disabled unless a directory is allowed.";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (args, script_args) = match args.iter().position(|a| a == "--") {
+        Some(i) => (&args[..i], Some(&args[i + 1..])),
+        None => (&args[..], None),
+    };
+    let (options, args): (Vec<&str>, Vec<&str>) = args.iter().map(String::as_str).partition(|a| a.starts_with("--"));
+    let script_args = script_args.unwrap_or_default();
+
+    let result = match (args.as_slice(), options.is_empty() && script_args.is_empty()) {
+        (["run", path] | [path], _) => run(path, &options, script_args),
+        (["assemble", path], true) => assemble(path, &Path::new(path).with_extension("asmb")).map(|()| 0),
+        (["assemble", path, "-o", out], true) => assemble(path, Path::new(out)).map(|()| 0),
+        (["disasm", path], true) => disasm(path).map(|()| 0),
+        (["debug", path], _) => debug(path, &options, script_args).map(|()| 0),
+        _ => Err(USAGE.to_string()),
+    };
+
+    match result {
+        Ok(code) => ExitCode::from(code),
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// Returns the exit code given to `exit`.
+fn run(path: &str, options: &[&str], args: &[String]) -> Result<u8, String> {
+    let (source, program) = load(path)?;
+    let mut vm = Vm::new(&program);
+    configure(&mut vm, options)?;
+    vm.set_args(args.iter().map(String::as_str));
+
+    vm.run().map_err(|e| e.render(&source))?;
+
+    Ok(vm.exit_code().unwrap_or(0))
+}
+
+fn assemble(path: &str, out: &Path) -> Result<(), String> {
+    let (_, program) = load(path)?;
+
+    std::fs::write(out, serialize(&program)).map_err(|e| format!("error: {}: {}", out.display(), e))
+}
+
+fn disasm(path: &str) -> Result<(), String> {
+    let (_, program) = load(path)?;
+    print!("{}", disassemble(&program));
+
+    Ok(())
+}
+
+fn debug(path: &str, options: &[&str], args: &[String]) -> Result<(), String> {
+    let (source, program) = load(path)?;
+    let mut vm = Vm::new(&program);
+    configure(&mut vm, options)?;
+    vm.set_args(args.iter().map(String::as_str));
+
+    // Commands and the program's input both come from stdin, neither side buffers anything past what
+    // it reads so a line typed for one isn't swallowed by the other.
+    vm.set_input(BufReader::with_capacity(1, io::stdin()));
+    let mut debugger = Debugger::new(vm, &source);
+
+    debugger.run(&mut BufReader::with_capacity(1, io::stdin()), &mut io::stdout())
+            .map_err(|e| format!("error: {}", e))
+}
+
+// Applies the `run` and `debug` options.
+fn configure(vm: &mut Vm, options: &[&str]) -> Result<(), String> {
+    let mut files = FilePolicy::disabled();
+
+    for option in options {
+        let unknown = || format!("error: unknown option `{}`\n{}", option, USAGE);
+
+        match option.split_once('=').unwrap_or((option, "")) {
+            ("--trace", "") => vm.set_trace(true),
+            ("--lenient", "") => vm.set_lenient(true),
+            ("--read-only", "") => files.set_read_only(true),
+            ("--overflow", mode) => vm.set_overflow(OverflowMode::parse(mode).ok_or_else(unknown)?),
+            ("--allow-dir", dir) if !dir.is_empty() => files.allow(dir).map_err(|e| format!("error: {}: {}", dir, e))?,
+            _ => return Err(unknown()),
+        }
+    }
+
+    vm.set_file_policy(files);
 
     Ok(())
 }
+
+// Reads a script, or a program assembled with `asm assemble`. The source text is empty for the latter.
+fn load(path: &str) -> Result<(Source, Program), String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("error: {}: {}", path, e))?;
+
+    if is_bytecode(&bytes) {
+        let source = Source::new(path, String::new());
+        let program = deserialize(path, &bytes).map_err(|e| e.render(&source))?;
+
+        return Ok((source, program));
+    }
+
+    let text = String::from_utf8(bytes).map_err(|_| format!("error: {}: not valid utf-8", path))?;
+    let source = Source::new(path, text);
+    let program = parse(&source).map_err(|e| e.render(&source))?;
+
+    Ok((source, program))
+}
@@ -0,0 +1,247 @@
+use crate::error::{Error, ErrorKind, Result};
+use crate::instructions::{canonical, Instruction, Type};
+use crate::interpreter::OverflowMode;
+use crate::lexer::{lex_line, Token};
+use crate::program::Program;
+use crate::span::{Source, Span, Spanned};
+use crate::stack::*;
+use std::str::FromStr;
+
+pub fn parse(source: &Source) -> Result<Program> {
+    let lines = source.text
+                      .split('\n')
+                      .enumerate()
+                      .map(|(i, s)| lex_line(i + 1, s))
+                      .filter(|t| !matches!(t, Ok(t) if t.is_empty()))
+                      .collect::<Result<Vec<_>>>()?;
+
+    let (spans, line_splits): (Vec<Span>, Vec<Vec<&str>>) = lines.iter().map(|t| Ok((line_span(t), words(t)?))).collect::<Result<Vec<_>>>()?.into_iter().unzip();
+
+    let labels: Labels = line_splits.iter().enumerate().filter_map(|(i, s)| find_label(i, s.as_slice())).collect();
+    let procedures: Procedures = find_procedures(line_splits.as_slice(), &spans)?;
+    let instructions: Vec<Spanned<Instruction>> = line_splits.iter()
+                                                             .zip(spans)
+                                                             .map(|(s, span)| match parse_instruction(s.as_slice(), &labels, &procedures) {
+                                                                 Ok(node) => Ok(Spanned { node, span }),
+                                                                 Err(e) => Err(e.at(span)),
+                                                             })
+                                                             .collect::<Result<_>>()?;
+
+    Ok(Program { name: source.name.clone(),
+                 instructions,
+                 labels: labels.iter().map(|(l, i)| (l.to_string(), *i)).collect(),
+                 procedures: procedures.iter().map(|(p, i)| (p.to_string(), *i)).collect() })
+}
+
+fn parse_instruction(s: &[&str], labels: &Labels, procedures: &Procedures) -> Result<Instruction> {
+    use Instruction::*;
+
+    Ok(match s {
+        ["pushint", x] => PushInt(parse_operand(x, Type::INT)?),
+        ["pushflt", x] => PushFlt(parse_operand(x, Type::FLOAT)?),
+        ["pushdbl", x] => PushDbl(parse_operand(x, Type::DOUBLE)?),
+        ["pushstr", x] => PushStr(x.to_string()),
+        ["pop"] => Pop,
+        ["dup"] => Dup,
+        ["swap"] => Swap,
+        ["clrstk"] => ClrStk, // clear table
+        ["dlcstk"] => DlcStk, // shrink_to_fit / dealloc table
+        ["add"] => Add,       // int
+        ["addf"] => AddF,     // float
+        ["sub"] => Sub,       // int
+        ["subf"] => SubF,     // float
+        ["mul"] => Mul,       // int
+        ["mulf"] => MulF,     // float
+        ["div"] => Div,       // int
+        ["divf"] => DivF,     // float
+        ["mod"] => Mod,       // int
+        ["modf"] => ModF,     // float
+        ["addd"] => AddD,     // double
+        ["subd"] => SubD,     // double
+        ["muld"] => MulD,     // double
+        ["divd"] => DivD,     // double
+        ["modd"] => ModD,     // double
+        ["itof"] => ItoF,
+        ["ftoi"] => FtoI,
+        ["itod"] => ItoD,
+        ["dtoi"] => DtoI,
+        ["ftod"] => FtoD,
+        ["dtof"] => DtoF,
+        ["itos"] => ItoS,
+        ["stoi"] => StoI,
+        ["ftos"] => FtoS,
+        ["stof"] => StoF,
+        ["dtos"] => DtoS,
+        ["stod"] => StoD,
+        ["strcat"] => StrCat,
+        ["strlen"] => StrLen,
+        ["substr"] => SubStr,
+        ["charat"] => CharAt,
+        ["strfind"] => StrFind,
+        ["strup"] => StrUp,
+        ["strlow"] => StrLow,
+        ["strtrim"] => StrTrim,
+        ["strsplit"] => StrSplit,
+        ["strcmp"] => StrCmp,
+        ["anew"] => ANew,
+        ["aload"] => ALoad,
+        ["astore"] => AStore,
+        ["alen"] => ALen,
+        ["apush"] => APush,
+        ["apop"] => APop,
+        ["mnew"] => MNew,
+        ["mset"] => MSet,
+        ["mget", l] => MGet(label(labels, l)?),
+        ["mdel"] => MDel,
+        ["mlen"] => MLen,
+        ["mkeys"] => MKeys,
+        ["incr"] => Incr,
+        ["decr"] => Decr,
+        ["and"] => And,
+        ["or"] => Or,
+        ["xor"] => Xor,
+        ["not"] => Not,
+        ["shl"] => Shl,
+        ["shr"] => Shr,
+        ["lshr"] => LShr,
+        ["popcnt"] => PopCnt,
+        ["rotl"] => RotL,
+        ["rotr"] => RotR,
+        ["mov", d, p] => Mov(parse_operand(d, Type::INT)?, parse_operand(p, Type::INT)?),
+        ["ld", d] => Ld(parse_operand(d, Type::INT)?),
+        ["dmphash", p] => DmpHash(parse_operand(p, Type::INT)?), // remove key
+        ["clrhash"] => ClrHash,                                  // clear table
+        ["dlchash"] => DlcHash,                                  // shrink_to_fit / dealloc table
+        ["dmpreg", p] => DmpReg(parse_operand(p, Type::INT)?),   // remove key
+        ["clrreg"] => ClrReg,                                    // clear table
+        ["dlcreg"] => DlcReg,                                    // shrink_to_fit / dealloc table
+        ["jmp", l] => Jmp(label(labels, l)?),
+        ["cmp", l] => Cmp(label(labels, l)?),
+        ["inthas", l] => IntHas(label(labels, l)?),
+        ["strhas", l] => StrHas(label(labels, l)?),
+        ["flthas", l] => FltHas(label(labels, l)?),
+        ["je", l] => JE(label(labels, l)?),     // int
+        ["jfe", l] => JFE(label(labels, l)?),   // float
+        ["jne", l] => JNE(label(labels, l)?),   // int
+        ["jfne", l] => JFNE(label(labels, l)?), // float
+        ["jge", l] => JGE(label(labels, l)?),   // int
+        ["jfge", l] => JFGE(label(labels, l)?), // float
+        ["jle", l] => JLE(label(labels, l)?),   // int
+        ["jfle", l] => JFLE(label(labels, l)?), // float
+        ["jgt", l] => JGT(label(labels, l)?),   // int
+        ["jfgt", l] => JFGT(label(labels, l)?), // float
+        ["jlt", l] => JLT(label(labels, l)?),   // int
+        ["jflt", l] => JFLT(label(labels, l)?), // float
+        ["jde", l] => JDE(label(labels, l)?),   // double
+        ["jdne", l] => JDNE(label(labels, l)?), // double
+        ["jdgt", l] => JDGT(label(labels, l)?), // double
+        ["jdlt", l] => JDLT(label(labels, l)?), // double
+        ["jdge", l] => JDGE(label(labels, l)?), // double
+        ["jdle", l] => JDLE(label(labels, l)?), // double
+        ["get", p] => Get(parse_operand(p, "index")?),
+        ["set", p] => Set(parse_operand(p, "index")?),
+        ["getarg", p] => GetArg(parse_operand(p, "index")?),
+        ["setarg", p] => SetArg(parse_operand(p, "index")?),
+        ["prnt"] => Prnt,
+        ["prntstr", d] => PrntStr(d.to_string()),
+        ["prntln"] => Prntln,
+        ["prntc"] => PrntC,
+        ["prntcln"] => PrntCln,
+        ["prntstk"] => PrntStk,
+        ["prntreg"] => PrntReg,
+        ["rdln", l] => RdLn(label(labels, l)?),
+        ["rdint", l] => RdInt(label(labels, l)?),
+        ["rdflt", l] => RdFlt(label(labels, l)?),
+        ["rdc"] => RdC,
+        ["argc"] => Argc,
+        ["argv"] => Argv,
+        ["exit", d] => Exit(parse_operand(d, "exit code from 0 to 255")?),
+        ["fopen"] => FOpen,
+        ["freadln", l] => FReadLn(label(labels, l)?),
+        ["freadall"] => FReadAll,
+        ["fwrite"] => FWrite,
+        ["fclose"] => FClose,
+        ["proc", proc] => Jmp(procedure(procedures, proc)?.1),
+        ["call", proc] => Call(procedure(procedures, proc)?.0 + 1),
+        ["ncall", name] => NCall(name.to_string()),
+        ["ret"] => Ret,
+        ["label", ..] | ["end"] => Noop,
+        ["#trace", x] => Trace(switch(x)?),
+        ["#lenient", x] => Lenient(switch(x)?),
+        ["#overflow", x] => Overflow(OverflowMode::parse(x).ok_or_else(|| ErrorKind::InvalidOperand(x.to_string(), "`#overflow` mode, expected `trap`, `wrap` or `saturate`"))?),
+        l => return Err(ErrorKind::InvalidInstruction(l.join(" ")).into()),
+    })
+}
+
+fn parse_operand<T: FromStr>(x: &str, t: &'static str) -> Result<T> {
+    x.parse::<T>().map_err(|_| ErrorKind::InvalidOperand(x.to_string(), t).into())
+}
+
+// The `on` / `off` setting of a directive.
+fn switch(x: &str) -> Result<bool> {
+    match x {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        x => Err(ErrorKind::InvalidOperand(x.to_string(), "setting, expected `on` or `off`").into()),
+    }
+}
+
+fn label(labels: &Labels, l: &str) -> Result<Pointer> {
+    labels.get(l).copied().ok_or_else(|| ErrorKind::UnknownLabel(l.to_string()).into())
+}
+
+fn procedure(procedures: &Procedures, p: &str) -> Result<(Pointer, Pointer)> {
+    procedures.get(p).copied().ok_or_else(|| ErrorKind::UnknownProcedure(p.to_string()).into())
+}
+
+fn find_label<'a>(i: Pointer, s: &'a [&'a str]) -> Option<Label<'a>> {
+    if let ["label", l] = s {
+        Some((l, i))
+    } else {
+        None
+    }
+}
+
+fn find_procedures<'a>(lines: &'a [Vec<&str>], spans: &[Span]) -> Result<Procedures<'a>> {
+    let mut ip = 0;
+    let mut res = Procedures::new();
+
+    while ip < lines.len() {
+        if let ["proc", proc_name] = lines[ip].as_slice() {
+            let start_ip = ip;
+            while lines[ip] != ["end"] {
+                ip += 1;
+
+                if ip == lines.len() {
+                    return Err(Error::from(ErrorKind::UnterminatedProcedure(proc_name.to_string())).at(spans[start_ip]));
+                }
+            }
+            res.insert(proc_name, (start_ip, ip + 1));
+        } else {
+            ip += 1;
+        }
+    }
+
+    Ok(res)
+}
+
+// Span from the first to the last token of a line.
+fn line_span(tokens: &[Spanned<Token>]) -> Span {
+    let (first, last) = (tokens[0].span, tokens[tokens.len() - 1].span);
+
+    Span { len: last.col + last.len - first.col,
+           ..first }
+}
+
+// The text of each token, with the mnemonic replaced by its canonical name so aliases and
+// differently cased spellings match the same instruction. Unknown mnemonics are left as written.
+fn words(tokens: &[Spanned<Token>]) -> Result<Vec<&str>> {
+    let mut words = tokens.iter().map(|t| t.node.text()).collect::<Vec<_>>();
+
+    match &tokens[0].node {
+        Token::Word(w) => words[0] = canonical(w).unwrap_or(w),
+        Token::Str(s) => return Err(Error::from(ErrorKind::InvalidInstruction(format!("{:?}", s))).at(tokens[0].span)),
+    }
+
+    Ok(words)
+}
@@ -0,0 +1,13 @@
+use crate::instructions::Instruction;
+use crate::span::Spanned;
+use crate::stack::Pointer;
+use std::collections::BTreeMap;
+
+// A parsed program, labels and procedures are resolved to pointers into `instructions`.
+#[derive(Debug)]
+pub struct Program {
+    pub name: String,
+    pub instructions: Vec<Spanned<Instruction>>,
+    pub labels: BTreeMap<String, Pointer>,
+    pub procedures: BTreeMap<String, (Pointer, Pointer)>, // (proc, end)
+}
@@ -0,0 +1,56 @@
+use std::fmt;
+
+// A location in the original source, `line` and `col` are 1-based and `len` is counted in chars.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub col: usize,
+    pub len: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.col)
+    }
+}
+
+#[derive(Debug)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+pub struct Source {
+    pub name: String,
+    pub text: String,
+}
+
+impl Source {
+    pub fn new(name: &str, text: String) -> Self {
+        Source { name: name.to_string(), text }
+    }
+
+    // `file:line:col`
+    pub fn location(&self, span: Span) -> String {
+        format!("{}:{}", self.name, span)
+    }
+
+    // Renders the line a span points at, with the span underlined by carets.
+    //
+    //   |
+    // 3 |     pop
+    //   |     ^^^
+    pub fn excerpt(&self, span: Span) -> String {
+        let line = self.text.lines().nth(span.line.saturating_sub(1)).unwrap_or("");
+        let gutter = span.line.to_string();
+        let pad = " ".repeat(gutter.len());
+
+        format!("{} |\n{} | {}\n{} | {}{}",
+                pad,
+                gutter,
+                line,
+                pad,
+                " ".repeat(span.col.saturating_sub(1)),
+                "^".repeat(span.len.max(1)))
+    }
+}
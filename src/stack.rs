@@ -1,28 +1,18 @@
-use crate::instructions::Instruction;
-use std::{
-    collections::{BTreeMap, HashMap},
-    isize,
-};
-
-// Fowler-Noll-Vo (FNV)
-// https://en.wikipedia.org/wiki/Fowler-Noll-Vo_hash_function
-pub fn hash(s: String) -> isize {
-    let mut hash = 2166136261;
-
-    for b in s.bytes() {
-        hash ^= b as u32;
-        hash = hash.wrapping_mul(16777619);
-    }
-
-    hash as isize
-}
+use crate::error::{ErrorKind, Result};
+use crate::files::Handle;
+use crate::instructions::Type;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::{self, Write};
+use std::rc::Rc;
 
 pub type Pointer = usize;
-pub type Program<'a> = &'a [Instruction];
 pub type Label<'a> = (&'a str, Pointer);
 pub type Labels<'a> = BTreeMap<&'a str, Pointer>;
 pub type Procedures<'a> = BTreeMap<&'a str, (Pointer, Pointer)>;
 
+#[derive(Debug, Copy, Clone)]
 pub struct StackFrame {
     pub stack_offset: Pointer,
     pub ip: Pointer,
@@ -30,21 +20,193 @@ pub struct StackFrame {
 
 pub type CallStack = Vec<StackFrame>;
 
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub struct StackValue {
-    pub value: isize,
-    pub hashed: bool,
-}
-#[derive(Debug)]
-pub enum ValueType {
-    String(String),
+// Strings are reference counted, so `dup`, `mov` and `get` share a single allocation which is
+// freed once the last stack slot or register holding it is gone. Arrays, maps and files are
+// handles in the same way, except they can be changed in place, which every copy of the handle sees.
+// Handles compare equal only when they refer to the same array, map or file, so a container holding
+// itself can be compared.
+pub enum StackValue {
+    Int(isize),
     Float(f32),
+    Double(f64),
+    Str(Rc<str>),
+    Array(Rc<RefCell<Vec<StackValue>>>),
+    Map(Rc<RefCell<BTreeMap<MapKey, StackValue>>>),
+    File(Handle),
+}
+
+// Maps are keyed by ints and strings, ints sort before strings.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MapKey {
+    Int(isize),
+    Str(Rc<str>),
+}
+
+impl MapKey {
+    pub fn from_value(v: &StackValue) -> Option<Self> {
+        match v {
+            StackValue::Int(i) => Some(MapKey::Int(*i)),
+            StackValue::Str(s) => Some(MapKey::Str(s.clone())),
+            _ => None,
+        }
+    }
+}
+
+impl From<MapKey> for StackValue {
+    fn from(key: MapKey) -> Self {
+        match key {
+            MapKey::Int(i) => StackValue::Int(i),
+            MapKey::Str(s) => StackValue::Str(s),
+        }
+    }
+}
+
+impl StackValue {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            StackValue::Int(_) => Type::INT,
+            StackValue::Float(_) => Type::FLOAT,
+            StackValue::Double(_) => Type::DOUBLE,
+            StackValue::Str(_) => Type::STRING,
+            StackValue::Array(_) => Type::ARRAY,
+            StackValue::Map(_) => Type::MAP,
+            StackValue::File(_) => Type::FILE,
+        }
+    }
+
+    // The number held by an int, float or double, `None` for a value of any other type.
+    pub fn as_int(&self) -> Option<isize> {
+        match self {
+            StackValue::Int(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_float(&self) -> Option<f32> {
+        match self {
+            StackValue::Float(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn as_double(&self) -> Option<f64> {
+        match self {
+            StackValue::Double(v) => Some(*v),
+            _ => None,
+        }
+    }
+}
+
+// Written out so it can be forced inline, `dup` and `get` clone on every call.
+impl Clone for StackValue {
+    #[inline(always)]
+    fn clone(&self) -> Self {
+        match self {
+            StackValue::Int(v) => StackValue::Int(*v),
+            StackValue::Float(v) => StackValue::Float(*v),
+            StackValue::Double(v) => StackValue::Double(*v),
+            StackValue::Str(v) => StackValue::Str(v.clone()),
+            StackValue::Array(v) => StackValue::Array(v.clone()),
+            StackValue::Map(v) => StackValue::Map(v.clone()),
+            StackValue::File(v) => StackValue::File(v.clone()),
+        }
+    }
+}
+
+impl PartialEq for StackValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (StackValue::Int(a), StackValue::Int(b)) => a == b,
+            (StackValue::Float(a), StackValue::Float(b)) => a == b,
+            (StackValue::Double(a), StackValue::Double(b)) => a == b,
+            (StackValue::Str(a), StackValue::Str(b)) => a == b,
+            (StackValue::Array(a), StackValue::Array(b)) => Rc::ptr_eq(a, b),
+            (StackValue::Map(a), StackValue::Map(b)) => Rc::ptr_eq(a, b),
+            (StackValue::File(a), StackValue::File(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+// The text `prnt` writes, strings are written without quotes unless they're in an array or a map.
+impl fmt::Display for StackValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_value(f, self, &mut Vec::new())
+    }
+}
+
+// Containers are written in their `prnt` form, which doesn't recurse forever on one holding itself.
+impl fmt::Debug for StackValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StackValue::Int(v) => f.debug_tuple("Int").field(v).finish(),
+            StackValue::Float(v) => f.debug_tuple("Float").field(v).finish(),
+            StackValue::Double(v) => f.debug_tuple("Double").field(v).finish(),
+            StackValue::Str(v) => f.debug_tuple("Str").field(v).finish(),
+            StackValue::Array(_) => write!(f, "Array({})", self),
+            StackValue::Map(_) => write!(f, "Map({})", self),
+            StackValue::File(h) => f.debug_tuple("File").field(h).finish(),
+        }
+    }
+}
+
+// `open` holds the containers being written, one that shows up inside itself is written as `[...]`
+// or `{...}`.
+fn write_value(f: &mut fmt::Formatter<'_>, v: &StackValue, open: &mut Vec<*const ()>) -> fmt::Result {
+    match v {
+        StackValue::Int(v) => write!(f, "{}", v),
+        StackValue::Float(v) => write!(f, "{}", v),
+        StackValue::Double(v) => write!(f, "{}", v),
+        StackValue::Str(v) => write!(f, "{}", v),
+        StackValue::Array(a) if open.contains(&(Rc::as_ptr(a) as *const ())) => write!(f, "[...]"),
+        StackValue::Map(m) if open.contains(&(Rc::as_ptr(m) as *const ())) => write!(f, "{{...}}"),
+        StackValue::Array(a) => {
+            open.push(Rc::as_ptr(a) as *const ());
+            write!(f, "[")?;
+
+            for (i, item) in a.borrow().iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write_item(f, item, open)?;
+            }
+
+            open.pop();
+            write!(f, "]")
+        }
+        StackValue::Map(m) => {
+            open.push(Rc::as_ptr(m) as *const ());
+            write!(f, "{{")?;
+
+            for (i, (key, item)) in m.borrow().iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write_item(f, &key.clone().into(), open)?;
+                write!(f, ": ")?;
+                write_item(f, item, open)?;
+            }
+
+            open.pop();
+            write!(f, "}}")
+        }
+        StackValue::File(h) => write!(f, "{}", h),
+    }
 }
 
+// A value inside an array or a map, strings are quoted so `"1"` and `1` can be told apart.
+fn write_item(f: &mut fmt::Formatter<'_>, v: &StackValue, open: &mut Vec<*const ()>) -> fmt::Result {
+    match v {
+        StackValue::Str(s) => write!(f, "{:?}", s),
+        v => write_value(f, v, open),
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct Stack {
     pub values: Vec<StackValue>,
-    pub hashmap: HashMap<isize, ValueType>,
     pub registers: HashMap<isize, StackValue>,
+    low: usize, // the fewest values there have been since `mark`, for the trace
 }
 
 impl Stack {
@@ -56,74 +218,125 @@ impl Stack {
         self.values.is_empty()
     }
 
-    pub fn print(&mut self) {
+    pub fn print(&mut self, w: &mut dyn Write) -> io::Result<()> {
         for value in &self.values {
-            let hash_str = if value.hashed {
-                format!(" --> Hash: ({:?})", self.hashmap.get(&value.value).unwrap_or(&ValueType::String("None".to_string())))
-            } else {
-                String::new()
-            };
-
-            println!("Stack: {}", value.value.to_string() + &hash_str);
+            writeln!(w, "Stack: {}", describe(value))?;
         }
+
+        Ok(())
     }
 
-    pub fn print_registers(&mut self) {
-        for value in &self.registers {
-            println!("Register: {:?}", self.registers.get(value.0))
+    pub fn print_registers(&mut self, w: &mut dyn Write) -> io::Result<()> {
+        let mut registers = self.registers.iter().collect::<Vec<_>>();
+        registers.sort_by_key(|(r, _)| **r);
+
+        for (r, value) in registers {
+            writeln!(w, "Register {}: {}", r, describe(value))?;
         }
+
+        Ok(())
+    }
+
+    // `push`, `pop`, `peek` and `get` are forced inline, `Vm::execute` is too large for the compiler
+    // to inline them on its own and the calls would cost more than the instructions using them.
+    #[inline(always)]
+    pub fn push(&mut self, v: StackValue) {
+        self.values.push(v);
+    }
+
+    #[inline(always)]
+    pub fn push_int(&mut self, v: isize) {
+        self.values.push(StackValue::Int(v));
+    }
+
+    #[inline(always)]
+    pub fn push_float(&mut self, v: f32) {
+        self.values.push(StackValue::Float(v));
     }
 
-    pub fn push_as_value(&mut self, v: isize) {
-        self.values.push(StackValue { value: v, hashed: false });
+    #[inline(always)]
+    pub fn push_double(&mut self, v: f64) {
+        self.values.push(StackValue::Double(v));
     }
 
-    pub fn push_as_hashed(&mut self, v: isize) {
-        self.values.push(StackValue { value: v, hashed: true });
+    pub fn push_str(&mut self, v: &str) {
+        self.values.push(StackValue::Str(v.into()));
     }
 
-    pub fn push_hashed_string(&mut self, v: &str) {
-        let h = hash(v.to_string());
-        self.values.push(StackValue { value: h, hashed: true });
-        self.hashmap.insert(h, ValueType::String(v.to_string()));
+    pub fn pop_int(&mut self) -> Result<isize> {
+        match self.pop()? {
+            StackValue::Int(v) => Ok(v),
+            v => Err(ErrorKind::TypeMismatch(Type::INT, v.type_name()).into()),
+        }
     }
 
-    pub fn push_hashed_float(&mut self, v: f32) {
-        let h = hash(v.to_string());
-        self.values.push(StackValue { value: h, hashed: true });
-        self.hashmap.insert(h, ValueType::Float(v));
+    pub fn pop_float(&mut self) -> Result<f32> {
+        match self.pop()? {
+            StackValue::Float(v) => Ok(v),
+            v => Err(ErrorKind::TypeMismatch(Type::FLOAT, v.type_name()).into()),
+        }
+    }
+
+    pub fn pop_double(&mut self) -> Result<f64> {
+        match self.pop()? {
+            StackValue::Double(v) => Ok(v),
+            v => Err(ErrorKind::TypeMismatch(Type::DOUBLE, v.type_name()).into()),
+        }
+    }
+
+    pub fn pop_string(&mut self) -> Result<Rc<str>> {
+        match self.pop()? {
+            StackValue::Str(v) => Ok(v),
+            v => Err(ErrorKind::TypeMismatch(Type::STRING, v.type_name()).into()),
+        }
     }
 
     pub fn push_register(&mut self, r: isize, v: StackValue) {
         self.registers.insert(r, v);
     }
 
-    pub fn pop(&mut self) -> StackValue {
-        self.values.pop().expect("popped an empty stack")
+    #[inline(always)]
+    pub fn pop(&mut self) -> Result<StackValue> {
+        let v = self.values.pop().ok_or_else(|| ErrorKind::StackUnderflow.into());
+        self.low = self.low.min(self.values.len());
+
+        v
     }
 
-    pub fn peek(&mut self) -> StackValue {
-        *self.values.last().expect("peeked an empty stack")
+    #[inline(always)]
+    pub fn peek(&mut self) -> Result<StackValue> {
+        self.values.last().cloned().ok_or_else(|| ErrorKind::StackUnderflow.into())
     }
 
-    pub fn peek_mut(&mut self) -> &mut StackValue {
-        self.values.last_mut().expect("peeked an empty stack")
+    #[inline(always)]
+    pub fn peek_mut(&mut self) -> Result<&mut StackValue> {
+        self.values.last_mut().ok_or_else(|| ErrorKind::StackUnderflow.into())
     }
 
-    pub fn get(&self, i: usize) -> &StackValue {
-        self.values.get(i).expect("accessed a nonexistent stack index")
+    #[inline(always)]
+    pub fn get(&self, i: usize) -> Result<&StackValue> {
+        self.values.get(i).ok_or_else(|| ErrorKind::InvalidIndex(i as i128).into())
     }
 
-    pub fn get_mut(&mut self, i: usize) -> &mut StackValue {
-        self.values.get_mut(i).expect("mutably accessed a nonexistent stack index")
+    #[inline(always)]
+    pub fn get_mut(&mut self, i: usize) -> Result<&mut StackValue> {
+        self.values.get_mut(i).ok_or_else(|| ErrorKind::InvalidIndex(i as i128).into())
     }
 
     pub fn clear_stack(&mut self) {
         self.values.clear();
+        self.low = 0;
+    }
+
+    // Starts tracking which values get popped, see `low`.
+    pub fn mark(&mut self) {
+        self.low = self.values.len();
     }
 
-    pub fn clear_hashmap(&mut self) {
-        self.hashmap.clear();
+    // The values below this index haven't been popped since `mark`, though they may have been
+    // changed in place. A value popped and pushed again is above it even if it reads the same.
+    pub fn low(&self) -> usize {
+        self.low
     }
 
     pub fn clear_registers(&mut self) {
@@ -134,19 +347,19 @@ impl Stack {
         self.registers.remove(&v);
     }
 
-    pub fn delete_hash(&mut self, v: isize) {
-        self.hashmap.remove(&v);
-    }
-
     pub fn shrink_stack(&mut self) {
         self.values.shrink_to_fit();
     }
 
-    pub fn shrink_hashmap(&mut self) {
-        self.hashmap.shrink_to_fit();
-    }
-
     pub fn shrink_registers(&mut self) {
         self.registers.shrink_to_fit();
     }
 }
+
+// `prntstk` / `prntreg` form of a value, the type followed by the value.
+pub fn describe(v: &StackValue) -> String {
+    match v {
+        StackValue::Str(s) => format!("{} {:?}", v.type_name(), s),
+        v => format!("{} {}", v.type_name(), v),
+    }
+}
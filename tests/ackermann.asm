@@ -1,3 +1,6 @@
+; A(3, 10), which prints `Stack: int 8189`. It runs for seconds, so it has no expectation and is skipped by `cargo test`.
+; Time it with `cargo run --release -- tests/ackermann.asm`, tests/ackermann_small.asm checks A(3, 3).
+
 Proc ackermann
     GetArg 1
     -- [m, n | m]
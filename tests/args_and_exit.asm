@@ -0,0 +1,25 @@
+; args: first second
+
+; output
+; 2
+; first
+; second
+
+; Print every argument, then exit before the last line.
+argc
+prntln
+pop
+
+pushint 0
+label next
+dup
+argv
+prntln
+pop
+incr
+dup
+argc
+sub
+jlt next
+exit 3
+prntstr "not printed"
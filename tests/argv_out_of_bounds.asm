@@ -0,0 +1,5 @@
+; args: only
+; error: index 1 is out of bounds for length 1 in `argv`
+
+pushint 1
+argv
@@ -0,0 +1,5 @@
+; error: `apop` on an empty array
+
+pushint 0
+anew
+apop
@@ -0,0 +1,4 @@
+; error: invalid size -1 in `anew`, expected 0 or more
+
+pushint -1
+anew
@@ -0,0 +1,6 @@
+; error: index 3 is out of bounds for length 3 in `aload`
+
+pushint 3
+anew
+pushint 3
+aload
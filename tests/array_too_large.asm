@@ -0,0 +1,4 @@
+; error: `anew` cannot allocate 1152921504606846976 items
+
+pushint 1152921504606846976
+anew
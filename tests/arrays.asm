@@ -0,0 +1,56 @@
+; output
+; [0, 1, 4, 9, 16]
+; 5
+; 9
+; [0, 1, 4, 9, 16, "six"]
+; six
+; 5
+; --> tests/arrays.asm:56:1
+; Stack: arr [0, 1, 4, 9, 16]
+
+; Fill an array of 5 with the square of each index.
+pushint 5
+anew
+pushint 0
+label fill
+get 0
+get 1
+dup
+dup
+mul
+astore
+incr
+dup
+pushint 5
+sub
+jlt fill
+pop
+pop
+prntln
+
+dup
+alen
+prntln
+pop
+
+dup
+pushint 3
+aload
+prntln
+pop
+
+; `dup` copies the handle, so both copies see the push.
+dup
+pushstr "six"
+apush
+prntln
+
+dup
+apop
+prntln
+pop
+dup
+alen
+prntln
+pop
+prntstk
@@ -0,0 +1,66 @@
+; output
+; 8
+; 14
+; 6
+; -13
+; 48
+; -4
+; 4611686018427387902
+; 3
+; 6
+; -9223372036854775808
+; 4611686018427387904
+
+pushint 12
+pushint 10
+and
+prntln
+pop
+pushint 12
+pushint 10
+or
+prntln
+pop
+pushint 12
+pushint 10
+xor
+prntln
+pop
+pushint 12
+not
+prntln
+pop
+
+pushint 3
+pushint 4
+shl
+prntln
+pop
+pushint -8
+pushint 1
+shr
+prntln
+pop
+pushint -8
+pushint 2
+lshr
+prntln
+pop
+
+pushint 7
+popcnt
+prntln
+pop
+
+pushint 3
+pushint 1
+rotl
+prntln
+pop
+pushint 1
+pushint 1
+rotr
+prntln
+pushint -1
+rotl
+prntln
@@ -0,0 +1,27 @@
+// Loads malformed bytecode, which has to be rejected with an error rather than a panic.
+use asm::bytecode::deserialize;
+
+// `ASMB` and version 1.
+const HEADER: &[u8] = &[b'A', b'S', b'M', b'B', 1, 0];
+
+fn load(body: &[u8]) -> String {
+    let bytes = [HEADER, body].concat();
+
+    deserialize("bad.asmb", &bytes).unwrap_err().to_string()
+}
+
+#[test]
+fn huge_string_length() {
+    // One string constant claiming `u64::MAX` bytes.
+    let error = load(&[1, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]);
+
+    assert!(error.contains("unexpected end of file"), "{}", error);
+}
+
+#[test]
+fn call_to_first_instruction() {
+    // No constants, a single `call 0` on line 1.
+    let error = load(&[0, 1, 57, 0, 1, 1, 4]);
+
+    assert!(error.contains("`call` to instruction 0"), "{}", error);
+}
@@ -0,0 +1,118 @@
+// Drives `asm debug` sessions from a scripted list of commands and checks the transcript.
+use asm::debugger::Debugger;
+use asm::instructions::Instruction;
+use asm::interpreter::Vm;
+use asm::parser::parse;
+use asm::program::Program;
+use asm::span::{Source, Span, Spanned};
+use std::collections::BTreeMap;
+
+const SCRIPT: &str = "pushint 2
+pushint 3
+call double
+label done
+prntstk
+
+proc double
+    getarg 0
+    pushint 2
+    mul
+    setarg 0
+    pop
+    ret
+end";
+
+// Skips the directive when the top of the stack is set to 0 before `je` runs, which pops it.
+const DIRECTIVE: &str = "pushint 1
+je skip
+#overflow wrap
+label skip
+pushint 9223372036854775807
+incr";
+
+fn session(commands: &str, check: impl FnOnce(&Debugger, &str)) {
+    session_on(SCRIPT, commands, check);
+}
+
+fn session_on(script: &str, commands: &str, check: impl FnOnce(&Debugger, &str)) {
+    let source = Source::new("debug.asm", script.to_string());
+    let program = parse(&source).unwrap();
+    let mut output = Vec::new();
+    let mut transcript = Vec::new();
+
+    {
+        let mut vm = Vm::new(&program);
+        vm.set_output(&mut output);
+
+        let mut debugger = Debugger::new(vm, &source);
+        debugger.run(&mut commands.as_bytes(), &mut transcript).unwrap();
+        check(&debugger, &String::from_utf8_lossy(&transcript));
+    }
+}
+
+#[test]
+fn breakpoints_and_frames() {
+    session("break double\nbreak done\ncontinue\nframes\ncontinue\nstack\n", |debugger, transcript| {
+        assert!(transcript.contains("breakpoint at debug.asm:8:5  getarg 0"), "{}", transcript);
+        assert!(transcript.contains("#0 double called from debug.asm:3:1, locals from [2]\n#1 top level"), "{}", transcript);
+        assert!(transcript.contains("debug.asm:4:1  label done"), "{}", transcript);
+        assert!(transcript.contains("[0] int 2\n[1] int 6\n"), "{}", transcript);
+        assert_eq!(debugger.vm().pointer(), 3);
+    });
+}
+
+#[test]
+fn modify_and_step() {
+    session("step 2\nset 1 10\nset 0 2.5d\nsetreg 7 \"x\"\nregs\ncontinue\nstack\nrestart\nstack\n",
+            |debugger, transcript| {
+                assert!(transcript.contains("debug.asm:3:1  call double"), "{}", transcript);
+                assert!(transcript.contains("Register 7: str \"x\""), "{}", transcript);
+                assert!(transcript.contains("program halted\n(asm) [0] dbl 2.5\n[1] int 20\n"), "{}", transcript);
+                assert!(debugger.vm().stack().values.is_empty());
+            });
+}
+
+#[test]
+fn restart_restores_directive_settings() {
+    session_on(DIRECTIVE, "continue\nrestart\nstep\nset 0 0\ncontinue\n", |_, transcript| {
+        assert!(transcript.contains("program halted"), "{}", transcript);
+        assert!(transcript.contains("integer overflow in `incr`"), "{}", transcript);
+    });
+}
+
+#[test]
+fn frames_of_a_call_to_the_first_instruction() {
+    // Bytecode can't hold this, but a program built by the host can.
+    let source = Source::new("built", String::new());
+    let program = Program { name: "built".to_string(),
+                            instructions: vec![Spanned { node: Instruction::Call(0),
+                                                         span: Span { line: 1, col: 1, len: 4 } }],
+                            labels: BTreeMap::new(),
+                            procedures: BTreeMap::new() };
+    let mut transcript = Vec::new();
+
+    let mut debugger = Debugger::new(Vm::new(&program), &source);
+    debugger.run(&mut "step\nframes\n".as_bytes(), &mut transcript).unwrap();
+
+    let transcript = String::from_utf8_lossy(&transcript);
+    assert!(transcript.contains("#0 ? called from built:1:1"), "{}", transcript);
+}
+
+#[test]
+fn continue_stops_on_a_breakpoint_at_the_first_instruction() {
+    session("break 1\ncontinue\nstack\ncontinue\n", |debugger, transcript| {
+        assert!(transcript.contains("(asm) debug.asm:1:1  pushint 2\n(asm) (asm) "), "{}", transcript);
+        assert!(transcript.contains("program halted"), "{}", transcript);
+        assert!(debugger.vm().is_halted());
+    });
+}
+
+#[test]
+fn set_refuses_values_that_are_not_finite() {
+    session("set 0 nan\nstep\nset 0 inf\nset 0 -inf\nset 0 1e39\nset 0 nand\nset 0 1e309d\nstack\n", |_, transcript| {
+        for value in ["nan", "inf", "-inf", "1e39", "nand", "1e309d"] {
+            assert!(transcript.contains(&format!("invalid value `{}`", value)), "{}", transcript);
+        }
+        assert!(transcript.contains("[0] int 2\n"), "{}", transcript);
+    });
+}
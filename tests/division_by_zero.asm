@@ -0,0 +1,5 @@
+; error: division by zero in `div`
+
+pushint 7
+pushint 0
+div
@@ -0,0 +1,51 @@
+; output
+; 0.30000000000000004
+; 0.3
+; 1.5
+; 1
+; -7
+; 2.5
+; positive
+; --> tests/doubles.asm:51:1
+; Stack: dbl 0.1
+
+; 0.1 + 0.2 in double precision, and rounded to a float
+pushdbl 0.1
+pushdbl 0.2
+addd
+prntln
+dtof
+prntln
+pop
+
+pushint 3
+itod
+pushdbl 2
+divd
+prntln
+dtoi
+prntln
+pop
+
+pushflt -7.9
+ftoi
+prntln
+pop
+
+pushint 5
+itof
+pushflt 2
+divf
+ftod
+prntln
+
+pushdbl 0.5
+subd
+jdgt positive
+prntstr "not positive"
+label positive
+prntstr "positive"
+
+clrstk
+pdbl 0.1
+prntstk
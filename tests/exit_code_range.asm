@@ -0,0 +1,3 @@
+; error: invalid operand: `256` is not a valid exit code from 0 to 255
+
+exit 256
@@ -1,3 +1,6 @@
+; fib(34), which prints 9227465. It runs for seconds, so it has no expectation and is skipped by `cargo test`.
+; Time it with `cargo run --release -- tests/fib_recurse.asm`, tests/fib_recurse_small.asm checks fib(20).
+
 proc fib
     decr
     jle retOne
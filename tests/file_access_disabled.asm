@@ -0,0 +1,5 @@
+; error: cannot open `tests/files/config.txt`: file access is disabled
+
+pushstr "tests/files/config.txt"
+pushstr "r"
+fopen
@@ -0,0 +1,7 @@
+; allow: tests/files
+; error: cannot open `tests/files/../hello_world.asm`: it is outside the allowed directories
+
+; `..` is resolved before the path is checked.
+pushstr "tests/files/../hello_world.asm"
+pushstr "r"
+fopen
@@ -0,0 +1,7 @@
+; allow: tests/files
+; read-only
+; error: cannot open `tests/files/report.txt`: file access is read-only
+
+pushstr "tests/files/report.txt"
+pushstr "w"
+fopen
@@ -0,0 +1,41 @@
+; allow: tests/files
+; read-only
+
+; output
+; <file "tests/files/config.txt">
+; name = demo
+; level = 3
+;
+; last line
+; 4 lines
+; name = demo
+; level = 3
+;
+; last line
+
+pushstr "tests/files/config.txt"
+pushstr "r"
+fopen
+prntln
+
+; Read line by line, counting the lines.
+pushint 0
+label next
+get 0
+freadln done
+prntln
+pop
+incr
+jmp next
+label done
+prnt
+prntstr " lines"
+pop
+fclose
+
+; `freadall` reads everything that's left, a fresh handle reads from the start.
+pushstr "tests/files/config.txt"
+pushstr "r"
+fopen
+freadall
+prnt
@@ -0,0 +1,130 @@
+// Writes files from scripts into a scratch directory and reads them back.
+use asm::files::FilePolicy;
+use asm::interpreter::Vm;
+use asm::parser::parse;
+use asm::span::Source;
+use std::path::{Path, PathBuf};
+
+const REPORT: &str = r#"pushstr "$DIR/report.txt"
+pushstr "w"
+fopen
+dup
+pushstr "total: "
+fwrite
+dup
+pushint 42
+fwrite
+fclose
+
+pushstr "$DIR/report.txt"
+pushstr "a"
+fopen
+dup
+pushstr "\ndone\n"
+fwrite
+fclose
+
+pushstr "$DIR/report.txt"
+pushstr "r"
+fopen
+freadall
+prnt"#;
+
+const WRITE_CLOSED: &str = r#"pushstr "$DIR/out.txt"
+pushstr "w"
+fopen
+dup
+fclose
+pushstr "late"
+fwrite"#;
+
+const WRITE_READER: &str = r#"pushstr "$DIR/out.txt"
+pushstr "r"
+fopen
+pushstr "x"
+fwrite"#;
+
+const BAD_MODE: &str = r#"pushstr "$DIR/out.txt"
+pushstr "rw"
+fopen"#;
+
+const SYMLINK: &str = r#"pushstr "$DIR/link.txt"
+pushstr "w"
+fopen
+pushstr "escaped"
+fwrite"#;
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("asm-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn run(dir: &Path, script: &str, policy: FilePolicy) -> (Result<(), String>, String) {
+    let script = script.replace("$DIR", &dir.to_string_lossy());
+    let source = Source::new("files.asm", script);
+    let program = parse(&source).unwrap();
+    let mut output = Vec::new();
+
+    let result = {
+        let mut vm = Vm::new(&program);
+        vm.set_output(&mut output);
+        vm.set_file_policy(policy);
+        vm.run().map_err(|e| e.to_string())
+    };
+
+    (result, String::from_utf8(output).unwrap())
+}
+
+#[test]
+fn write_append_and_read_back() {
+    let dir = scratch("write");
+    let mut policy = FilePolicy::disabled();
+    policy.allow(&dir).unwrap();
+
+    let (result, output) = run(&dir, REPORT, policy);
+
+    result.unwrap();
+    assert_eq!(output, "total: 42\ndone\n");
+    assert_eq!(std::fs::read_to_string(dir.join("report.txt")).unwrap(), "total: 42\ndone\n");
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn closed_and_misused_handles() {
+    let dir = scratch("closed");
+    let mut policy = FilePolicy::disabled();
+    policy.allow(&dir).unwrap();
+
+    let (result, _) = run(&dir, WRITE_CLOSED, policy.clone());
+    assert!(result.unwrap_err().contains("`fwrite` on a closed file"));
+
+    let (result, _) = run(&dir, WRITE_READER, policy.clone());
+    assert!(result.unwrap_err().contains("`fwrite` on a file opened for reading"));
+
+    let (result, _) = run(&dir, BAD_MODE, policy);
+    assert!(result.unwrap_err().contains("invalid file mode `rw`, expected r, w or a"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn dangling_symlink_is_not_followed() {
+    let dir = scratch("symlink");
+    let sandbox = dir.join("sandbox");
+    let outside = dir.join("outside");
+    std::fs::create_dir_all(&sandbox).unwrap();
+    std::fs::create_dir_all(&outside).unwrap();
+    std::os::unix::fs::symlink(outside.join("pwned.txt"), sandbox.join("link.txt")).unwrap();
+
+    let mut policy = FilePolicy::disabled();
+    policy.allow(&sandbox).unwrap();
+
+    let (result, _) = run(&sandbox, SYMLINK, policy);
+    assert!(result.unwrap_err().contains("it is a symlink to a file that doesn't exist"));
+    assert!(!outside.join("pwned.txt").exists());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
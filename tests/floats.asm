@@ -1,3 +1,7 @@
+; output
+; --> tests/floats.asm:8:1
+; Stack: flt 4.5
+
 PushFloat 2.0
 PushFloat 2.5
 AddF
@@ -0,0 +1,9 @@
+; error: invalid stack index: 5
+
+pushint 1
+pushint 2
+call read
+proc read
+    pushint 3
+    get 5
+end
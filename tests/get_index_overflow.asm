@@ -0,0 +1,7 @@
+; error: invalid stack index: 18446744073709551615
+
+pushint 1
+call read
+proc read
+    get 18446744073709551615
+end
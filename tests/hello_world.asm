@@ -1,3 +1,8 @@
+; output
+; Hello World
+; --> tests/hello_world.asm:74:1
+; Stack: int 0
+
 Proc printStr
     -- [..., i | ]
     GetArg 1
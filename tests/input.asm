@@ -0,0 +1,53 @@
+; input
+; 12
+;   -3
+; seven
+; 2.5
+; hello world
+; ab
+
+; output
+; 9
+; 2.5
+; hello world
+; a
+; b
+; 10
+; end of input
+; --> tests/input.asm:53:1
+; Stack: int 9
+; Stack: flt 2.5
+; Stack: str "hello world"
+; Stack: int 97
+; Stack: int 98
+; Stack: int 10
+; Stack: int -1
+
+; Sum ints until a line isn't one, surrounding whitespace is ignored. The line that failed to
+; parse, `seven`, is consumed.
+pushint 0
+label sum
+rdint summed
+add
+jmp sum
+label summed
+prntln
+
+rdflt done
+prntln
+
+rdln done
+prntln
+
+; `rdc` reads the rest byte by byte, the newline included, and pushes -1 at the end.
+rdc
+prntcln
+rdc
+prntcln
+rdc
+prntln
+rdc
+rdln done
+label done
+prntstr "end of input"
+prntstk
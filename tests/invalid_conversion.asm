@@ -0,0 +1,4 @@
+; error: `dtoi` cannot convert `10000000000000000000` to int
+
+pushdbl 1e19
+dtoi
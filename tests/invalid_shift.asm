@@ -0,0 +1,5 @@
+; error: shift amount 64 is out of range in `shl`, expected 0 to 63
+
+pushint 1
+pushint 64
+shl
@@ -0,0 +1,22 @@
+; output
+; --> tests/lenient.asm:17:1
+; Stack: int 1
+; Stack: str "x"
+
+; error: type mismatch in `je`: expected int, found str
+
+; Without `#lenient on` every one of these instructions would fail.
+#lenient on
+pushint 1
+pushint 2
+pushflt 2.5
+add
+pushstr "x"
+incr
+je never
+prntstk
+
+#lenient off
+je never
+
+label never
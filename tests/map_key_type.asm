@@ -0,0 +1,6 @@
+; error: type mismatch in `mset`: expected map, int or str, any, found map, flt, int
+
+mnew
+pushflt 1.5
+pushint 1
+mset
@@ -0,0 +1,53 @@
+; output
+; {1: "one", "two": 2}
+; 2
+; one
+; missing
+; {"two": 2}
+; ["two"]
+; 1
+; --> tests/maps.asm:53:1
+; Stack: map {"two": 2}
+
+mnew
+; Keys are kept in order, ints before strings.
+dup
+pushstr "two"
+pushint 2
+mset
+dup
+pushint 1
+pushstr "one"
+mset
+prntln
+
+dup
+mlen
+prntln
+pop
+
+dup
+pushint 1
+mget missing
+prntln
+pop
+
+; `"1"` isn't the same key as `1`.
+dup
+pushstr "1"
+mget missing
+label missing
+prntstr "missing"
+
+dup
+pushint 1
+mdel
+prntln
+
+dup
+mkeys
+prntln
+alen
+prntln
+pop
+prntstk
@@ -1,3 +1,7 @@
+; output
+; --> tests/math.asm:27:5
+; Stack: int 0
+
 Proc math
     GetArg 0
     GetArg 1
@@ -1,6 +1,15 @@
+; output
+; --> tests/memory_management.asm:11:1
+; Stack: str "hello"
+;
+; Register 0: str "hello"
+;
+; --> tests/memory_management.asm:27:1
+; Stack: str "hello"
+
 PushStr hello
 PrintStack
-; Stack: 1335831723 --> Hash: (String("hello"))
+; Stack: str "hello"
 
 PushInt 10
 PrintC
@@ -8,7 +17,7 @@ Pop
 
 Mov 0 -1
 PrintRegisters
--- Register: Some(StackValue { value: 1335831723, hashed: true })
+-- Register 0: str "hello"
 
 PushInt 10
 PrintC
@@ -16,5 +25,5 @@ Pop
 
 DmpHash -1
 PrintStack
--- Stack: 1335831723 --> Hash: (String("None"))
-; Value has been deleted from the table
+-- Stack: str "hello"
+; Strings are freed once nothing references them, dmphash leaves the value intact
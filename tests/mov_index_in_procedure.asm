@@ -0,0 +1,9 @@
+; error: invalid stack index: 4
+
+pushint 1
+call read
+proc read
+    pushint 2
+    pushint 3
+    mov 0 4
+end
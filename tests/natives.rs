@@ -0,0 +1,74 @@
+// Registers native functions on a `Vm` and calls them from scripts with `ncall`.
+use asm::error::ErrorKind;
+use asm::interpreter::Vm;
+use asm::parser::parse;
+use asm::span::Source;
+use asm::stack::StackValue;
+
+const GREET: &str = "pushstr \"world\"
+pushint 2
+ncall greet
+prntln";
+
+const MISSING: &str = "pushint 1
+ncall missing";
+
+const FAILING: &str = "pushint 1
+pushint 2
+ncall fail";
+
+#[test]
+fn pops_and_pushes() {
+    let source = Source::new("natives.asm", GREET.to_string());
+    let program = parse(&source).unwrap();
+    let mut output = Vec::new();
+    let mut popped = Vec::new();
+
+    {
+        let mut vm = Vm::new(&program);
+        vm.set_output(&mut output);
+        vm.register("greet", |stack| {
+              let times = stack.pop_int()?;
+              let name = stack.pop_string()?;
+              popped.push((times, name.to_string()));
+              stack.push_str(&format!("hello {}", name).repeat(times as usize));
+
+              Ok(())
+          });
+
+        vm.run().unwrap();
+        assert_eq!(vm.stack().values, vec![StackValue::Str("hello worldhello world".into())]);
+    }
+
+    assert_eq!(popped, vec![(2, "world".to_string())]);
+    assert_eq!(String::from_utf8(output).unwrap(), "hello worldhello world\n");
+}
+
+#[test]
+fn unknown_native() {
+    let source = Source::new("natives.asm", MISSING.to_string());
+    let program = parse(&source).unwrap();
+
+    let error = Vm::new(&program).run().unwrap_err();
+
+    assert!(matches!(&error.kind, ErrorKind::UnknownNative(name) if name == "missing"), "{:?}", error);
+    assert_eq!(error.span, Some(program.instructions[1].span));
+}
+
+#[test]
+fn native_error_points_at_ncall() {
+    let source = Source::new("natives.asm", FAILING.to_string());
+    let program = parse(&source).unwrap();
+
+    let mut vm = Vm::new(&program);
+    vm.register("fail", |stack| {
+          stack.pop_int()?;
+          Err(ErrorKind::Native("no thanks".to_string()).into())
+      });
+
+    let error = vm.run().unwrap_err();
+
+    assert_eq!(error.to_string(), "3:1: native function failed: no thanks");
+    assert_eq!(error.span, Some(program.instructions[2].span));
+    assert_eq!(vm.stack().values, vec![StackValue::Int(1)]);
+}
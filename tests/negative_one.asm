@@ -1,3 +1,11 @@
+; output
+; --> tests/negative_one.asm:13:1
+; Stack: str "hello"
+; Stack: str "world"
+; Stack: str ":)"
+; Register 0: int 99999
+; Register 1: int 99999
+
 pushstr hello
 pushstr world
 pushstr :)
@@ -0,0 +1,35 @@
+; output
+; -9223372036854775808
+; 9223372036854775807
+; -9223372036854775808
+; 0
+; -2
+
+#overflow wrap
+pushint 9223372036854775807
+incr
+prntln
+pop
+
+#overflow saturate
+pushint 9223372036854775807
+pushint 2
+mul
+prntln
+pop
+pushint -9223372036854775807
+pushint 5
+sub
+prntln
+
+; isize::MIN % -1 is 0 in every mode but trap
+pushint -1
+mod
+prntln
+pop
+
+#overflow trap
+pushint -7
+pushint 3
+div
+prntln
@@ -0,0 +1,4 @@
+; error: integer overflow in `incr`
+
+pushint 9223372036854775807
+incr
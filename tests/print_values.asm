@@ -0,0 +1,16 @@
+; output
+; hello world
+; 2.5
+; 42-7
+; H
+
+pushstr "hello world"
+prntln
+pushflt 2.5
+prntln
+pushint 42
+prnt
+pushint -7
+prntln
+pushint 72
+prntcln
@@ -1,3 +1,13 @@
+; output
+; --> tests/procedure.asm:28:1
+; Stack: int 3
+; Stack: int 1
+; Stack: int 2
+; --> tests/procedure.asm:30:1
+; Stack: int 9
+; --> tests/procedure.asm:32:1
+; Stack: int 81
+
 Push 3
 Push 1
 Push 2
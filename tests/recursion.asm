@@ -1,3 +1,6 @@
+; output
+; 3628800
+
 Proc factorial
     JE retOne
 
@@ -1,3 +1,6 @@
+; output
+; --> tests/registers.asm:13:1
+
 PushStr hello
 Mov 0 0
 Pop
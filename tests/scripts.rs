@@ -0,0 +1,256 @@
+// Runs every `.asm` script under `tests/` and `samples/` and compares its output against the
+// expectation written in the script's leading comment block.
+//
+// ; output              every comment line that follows, up to the first blank or
+// ; line 1              non-comment line, is one expected line of output
+// ; line 2
+//
+// -- expect: line 1     a single expected line, may be repeated
+// -- error: underflow   the script must fail with an error containing this text
+//
+// ; input               what the `rd*` instructions read, written the same way as the output,
+// ; line 1              as a block or with `-- input: line 1`; scripts without any read nothing
+//
+// -- args: a b          the arguments `argc` and `argv` see, separated by whitespace
+// -- allow: tests/files a directory `fopen` may open files under, file access is disabled otherwise
+// -- read-only          `fopen` may only open files for reading
+//
+// The trace of scripts using `#trace on` is interleaved with their output.
+//
+// Scripts without an expectation are reported as skipped. Every script is also assembled to
+// bytecode and loaded back, and the bytecode is disassembled and parsed again; both must behave
+// the same as the parsed program. Trace lines are left out of those comparisons since bytecode
+// doesn't keep names, as are the `-->` lines `prntstk` prints for the disassembly.
+use asm::bytecode::{deserialize, serialize};
+use asm::disasm::disassemble;
+use asm::error::Result as VmResult;
+use asm::files::FilePolicy;
+use asm::interpreter::Vm;
+use asm::parser::parse;
+use asm::program::Program;
+use asm::span::Source;
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+// Guards against scripts that never halt.
+const MAX_STEPS: usize = 10_000_000;
+
+#[derive(Default)]
+struct Expectation {
+    args: Vec<String>,
+    files: FilePolicy,
+    input: Vec<String>,
+    output: Vec<String>,
+    error: Option<String>,
+}
+
+#[test]
+fn scripts() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut failures = Vec::new();
+
+    for path in scripts_in(&root.join("tests")).into_iter().chain(scripts_in(&root.join("samples"))) {
+        let name = path.strip_prefix(root).unwrap().to_string_lossy().replace('\\', "/");
+        let text = std::fs::read_to_string(&path).unwrap();
+
+        let Some(expected) = expectation(&text) else {
+            eprintln!("skipped {} (no expectation)", name);
+            continue;
+        };
+
+        if let Err(e) = check(&Source::new(&name, text), &expected) {
+            failures.push(format!("{}\n{}", name, e));
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n\n"));
+}
+
+fn scripts_in(dir: &Path) -> Vec<PathBuf> {
+    let mut paths = std::fs::read_dir(dir).unwrap()
+                                          .map(|e| e.unwrap().path())
+                                          .filter(|p| p.extension().is_some_and(|e| e == "asm"))
+                                          .collect::<Vec<_>>();
+    paths.sort();
+    paths
+}
+
+fn comment(line: &str) -> Option<&str> {
+    let line = line.trim();
+    let body = line.strip_prefix(';').or_else(|| line.strip_prefix("--"))?;
+
+    Some(body.strip_prefix(' ').unwrap_or(body).trim_end())
+}
+
+fn expectation(text: &str) -> Option<Expectation> {
+    let mut expected = Expectation::default();
+    let mut found = false;
+    let mut in_output = false;
+    let mut in_input = false;
+
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            in_output = false;
+            in_input = false;
+            continue;
+        }
+
+        let Some(body) = comment(line) else {
+            break;
+        };
+
+        if in_output {
+            expected.output.push(body.to_string());
+        } else if in_input {
+            expected.input.push(body.to_string());
+        } else if body == "output" {
+            in_output = true;
+            found = true;
+        } else if body == "input" {
+            in_input = true;
+        } else if let Some(a) = body.strip_prefix("args:") {
+            expected.args.extend(a.split_whitespace().map(str::to_string));
+        } else if let Some(dir) = body.strip_prefix("allow:") {
+            expected.files.allow(Path::new(env!("CARGO_MANIFEST_DIR")).join(dir.trim())).unwrap();
+        } else if body == "read-only" {
+            expected.files.set_read_only(true);
+        } else if let Some(l) = body.strip_prefix("input:") {
+            expected.input.push(l.strip_prefix(' ').unwrap_or(l).to_string());
+        } else if let Some(l) = body.strip_prefix("expect:") {
+            expected.output.push(l.strip_prefix(' ').unwrap_or(l).to_string());
+            found = true;
+        } else if let Some(e) = body.strip_prefix("error:") {
+            expected.error = Some(e.trim().to_string());
+            found = true;
+        }
+    }
+
+    found.then_some(expected)
+}
+
+fn check(source: &Source, expected: &Expectation) -> Result<(), String> {
+    let program = match parse(source) {
+        Ok(program) => program,
+        Err(e) => return compare(source, expected, (Err(e), Vec::new())),
+    };
+
+    compare(source, expected, execute(&program, expected))?;
+
+    // Bytecode has no label or procedure names, so its trace lines read differently.
+    let program = deserialize(&source.name, &serialize(&program)).map_err(|e| format!("bytecode failed to load\n{}", e))?;
+    compare_lines(source, expected, execute(&program, expected), |l| !is_trace(l)).map_err(|e| format!("(bytecode)\n{}", e))?;
+
+    // The disassembly has its own line numbers as well.
+    let disassembled = Source::new(&source.name, disassemble(&program));
+    let program = parse(&disassembled).map_err(|e| format!("disassembly failed to parse\n{}", e.render(&disassembled)))?;
+    compare_lines(source, expected, execute(&program, expected), |l| !is_trace(l) && !l.starts_with("--> ")).map_err(|e| format!("(disassembled)\n{}", e))
+}
+
+// Compares only the expected and actual lines `keep` accepts.
+fn compare_lines(source: &Source, expected: &Expectation, (result, output): (VmResult<bool>, Vec<u8>), keep: impl Fn(&str) -> bool) -> Result<(), String> {
+    let expected = Expectation { args: Vec::new(),
+                                 files: FilePolicy::disabled(),
+                                 input: Vec::new(),
+                                 output: expected.output.iter().filter(|l| keep(l)).cloned().collect(),
+                                 error: expected.error.clone() };
+    let output = String::from_utf8_lossy(&output).lines().filter(|l| keep(l)).flat_map(|l| [l, "\n"]).collect::<String>();
+
+    compare(source, &expected, (result, output.into_bytes()))
+}
+
+// Trace lines start with the index of the instruction, `0012 jmp end`.
+fn is_trace(line: &str) -> bool {
+    let b = line.as_bytes();
+    b.len() > 5 && b[..4].iter().all(u8::is_ascii_digit) && b[4] == b' '
+}
+
+// Runs a program with the arguments and input of `expected`, returning whether it halted and what
+// it printed.
+fn execute(program: &Program, expected: &Expectation) -> (VmResult<bool>, Vec<u8>) {
+    let output = Shared::default();
+    let input = expected.input.iter().flat_map(|l| [l.as_str(), "\n"]).collect::<String>();
+
+    let halted = {
+        let mut vm = Vm::new(program);
+        vm.set_input(input.as_bytes());
+        vm.set_args(expected.args.iter().map(String::as_str));
+        vm.set_file_policy(expected.files.clone());
+        vm.set_output(output.clone());
+        vm.set_trace_output(output.clone());
+        step_bounded(&mut vm)
+    };
+
+    (halted, output.0.take())
+}
+
+// A buffer the output and the trace both write to.
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn step_bounded(vm: &mut Vm) -> VmResult<bool> {
+    for _ in 0..MAX_STEPS {
+        if !vm.step()? {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+fn compare(source: &Source, expected: &Expectation, (result, output): (VmResult<bool>, Vec<u8>)) -> Result<(), String> {
+    let output = String::from_utf8_lossy(&output);
+    let mut actual = output.lines().map(|l| l.trim_end()).collect::<Vec<_>>();
+
+    while actual.last() == Some(&"") {
+        actual.pop();
+    }
+
+    match (result, &expected.error) {
+        (Ok(false), _) => return Err(format!("did not halt within {} steps", MAX_STEPS)),
+        (Ok(true), Some(e)) => return Err(format!("expected an error containing `{}`, but the script succeeded", e)),
+        (Err(err), None) => return Err(format!("unexpected error\n{}", err.render(source))),
+        (Err(err), Some(e)) if !err.to_string().contains(e.as_str()) => {
+            return Err(format!("expected an error containing `{}`, found\n{}", e, err.render(source)));
+        }
+        _ => {}
+    }
+
+    if actual == expected.output {
+        Ok(())
+    } else {
+        Err(diff(&expected.output, &actual))
+    }
+}
+
+// Line by line diff, `-` is expected and `+` is actual output.
+fn diff(expected: &[String], actual: &[&str]) -> String {
+    let mut out = Vec::new();
+
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => out.push(format!("  {}", e)),
+            (e, a) => {
+                if let Some(e) = e {
+                    out.push(format!("- {}", e));
+                }
+                if let Some(a) = a {
+                    out.push(format!("+ {}", a));
+                }
+            }
+        }
+    }
+
+    out.join("\n")
+}
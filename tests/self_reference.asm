@@ -0,0 +1,43 @@
+; output
+; [1, [...]]
+; same
+; {"me": {...}}
+; [[], []]
+
+; An array holding itself prints as `[...]` where it repeats, and compares by identity.
+pushint 0
+anew
+dup
+pushint 1
+apush
+dup
+dup
+apush
+prntln
+dup
+cmp equal
+prntstr "different"
+label equal
+prntstr "same"
+pop
+
+mnew
+dup
+pushstr "me"
+get 0
+mset
+prntln
+pop
+
+; The same array twice side by side isn't a cycle.
+pushint 0
+anew
+pushint 0
+anew
+dup
+get 0
+apush
+dup
+get 0
+apush
+prntln
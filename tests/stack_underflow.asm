@@ -0,0 +1,5 @@
+; error: stack underflow
+
+pushint 1
+pop
+pop
@@ -0,0 +1,39 @@
+; output
+; 42
+; 43
+; 2.5
+; 1.5
+; 0.30000000000000004
+; 0.1
+; --> tests/string_conversions.asm:39:1
+; Stack: str "-7"
+
+pushint 42
+itos
+prntln
+pushstr " 43 "
+stoi
+prntln
+clrstk
+
+pushflt 2.5
+ftos
+prntln
+pushstr "1.5"
+stof
+prntln
+clrstk
+
+pushdbl 0.1
+pushdbl 0.2
+addd
+dtos
+prntln
+pushstr "0.1"
+stod
+prntln
+clrstk
+
+pushint -7
+itos
+prntstk
@@ -0,0 +1,12 @@
+; output
+; hello world
+; tab	here "quoted" \ back
+; second line
+; é ✓
+; plain
+
+pushstr "hello world" ; trailing comment
+prntstr "hello world" -- also a comment
+prntstr "tab\there \"quoted\" \\ back\nsecond line"
+prntstr "\u{e9} \u{2713}"
+prntstr plain
@@ -0,0 +1,89 @@
+; output
+; hello, world
+; 12
+; world
+; 5
+; e
+; 7
+; -1
+; HELLO, WORLD
+; hello, world
+; padded
+; --> tests/strings.asm:72:1
+; Stack: str "a"
+; Stack: str "b"
+; Stack: str ""
+; Stack: str "c"
+; Stack: int 4
+; -1
+; 0
+; 1
+
+pushstr "hello"
+pushstr ", world"
+strcat
+prntln
+dup
+strlen
+prntln
+pop
+
+dup
+pushint 7
+pushint 5
+substr
+prntln
+strlen
+prntln
+pop
+
+dup
+pushint 1
+charat
+prntln
+pop
+
+dup
+pushstr "world"
+strfind
+prntln
+pop
+dup
+pushstr "moon"
+strfind
+prntln
+pop
+
+dup
+strup
+prntln
+strlow
+prntln
+clrstk
+
+pushstr "  padded\t"
+strtrim
+prntln
+pop
+
+pushstr "a,b,,c"
+pushstr ","
+strsplit
+prntstk
+clrstk
+
+pushstr "apple"
+pushstr "banana"
+strcmp
+prntln
+pop
+pushstr "pear"
+pushstr "pear"
+strcmp
+prntln
+je equal
+label equal
+pushstr "b"
+pushstr "a"
+strcmp
+prntln
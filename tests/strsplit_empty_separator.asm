@@ -0,0 +1,5 @@
+; error: empty separator in `strsplit`
+
+pushstr "abc"
+pushstr ""
+strsplit
@@ -0,0 +1,6 @@
+; error: invalid length -1 in `substr`, expected 0 or more
+
+pushstr "hello"
+pushint 1
+pushint -1
+substr
@@ -0,0 +1,6 @@
+; error: index 6 is out of bounds for length 5 in `substr`
+
+pushstr "hello"
+pushint 2
+pushint 4
+substr
@@ -1,3 +1,6 @@
+; output
+; 4950
+
 Push 0
 Push 0
 
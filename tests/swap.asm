@@ -1,3 +1,11 @@
+; output
+; --> tests/swap.asm:12:1
+; Stack: int 10
+; Stack: int 5
+; --> tests/swap.asm:14:1
+; Stack: int 5
+; Stack: int 10
+
 pushint 5
 pushint 10
 swap
@@ -1,3 +1,8 @@
+; output
+; --> tests/test.asm:20:1
+; Stack: str "value1/value"
+; Stack: int 69696969
+
 Proc helloworld
     PushStr value1/value
     PushStr value1/
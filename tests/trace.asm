@@ -0,0 +1,71 @@
+; output
+; 0002 label again
+; 0003 decr                            pop int 2; push int 1
+; 1
+; 0004 prntln
+; 0005 dup                             push int 1
+; 0006 jne again                       pop int 1; jump 0002
+; 0002 label again
+; 0003 decr                            pop int 1; push int 0
+; 0
+; 0004 prntln
+; 0005 dup                             push int 0
+; 0006 jne again
+; 0007 pushint 2                       push int 2
+; 0008 pushint 5                       push int 5
+; 0009 sub                             pop int 2, int 5; push int -3
+; 0010 jlt done                        pop int -3; jump 0012
+; 0012 label done
+; 0013 pushstr "a b"                   push str "a b"
+; 0014 pushint 7                       push int 7
+; 0015 get 2                           push str "a b"
+; 0016 set 1                           [1] int 0 => str "a b"
+; 0017 pop                             pop str "a b"
+; 0018 pushint 1                       push int 1
+; 0019 pushint 1                       push int 1
+; 0020 swap                            pop int 1, int 1; push int 1, int 1
+; 0021 cmp equal                       pop int 1, int 1; push int 1
+; 0022 label equal
+; 0023 pop                             pop int 1
+; 0024 pushint 2                       push int 2
+; 0025 anew                            pop int 2; push arr [0, 0]
+; 0026 dup                             push arr [0, 0]
+; 0027 pushint 0                       push int 0
+; 0028 pushint 7                       push int 7
+; 0029 astore                          [4] arr [0, 0] => arr [7, 0]; pop arr [0, 0], int 0, int 7
+; 0030 pop                             pop arr [7, 0]
+; 7
+
+pushint 2
+#trace on
+label again
+decr
+prntln
+dup
+jne again
+pushint 2
+pushint 5
+sub
+jlt done
+pushstr "skipped"
+label done
+pushstr "a b"
+pushint 7
+get 2
+set 1
+pop
+pushint 1
+pushint 1
+swap
+cmp equal
+label equal
+pop
+pushint 2
+anew
+dup
+pushint 0
+pushint 7
+astore
+pop
+#trace off
+prntln
@@ -0,0 +1,5 @@
+; error: type mismatch in `addf`: expected flt, flt, found int, flt
+
+pushint 1
+pushflt 2.5
+addf
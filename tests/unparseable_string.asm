@@ -0,0 +1,4 @@
+; error: `stoi` cannot convert `12abc` to int
+
+pushstr "12abc"
+stoi
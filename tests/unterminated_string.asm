@@ -0,0 +1,3 @@
+; error: unterminated string literal
+
+prntstr "never closed